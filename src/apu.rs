@@ -0,0 +1,579 @@
+pub mod noise;
+pub mod pulse;
+pub mod resampler;
+pub mod wave;
+
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::resampler::{ HighPassFilter, Resampler, SAMPLE_RATE_48000 };
+use crate::apu::wave::Wave;
+use crate::model::Model;
+
+/// The channels are clocked and sampled once per M-cycle.
+pub const SAMPLE_CYCLES: u32 = 4;
+
+/// Samples per second the APU produces, about 1 MHz.
+pub const SAMPLE_RATE: u32 = 4_194_304 / SAMPLE_CYCLES;
+
+/// The frame sequencer steps at 512 Hz, clocking the length counters on
+/// even steps, the sweep on steps 2 and 6 and the envelopes on step 7.
+const FRAME_STEPS: u8 = 8;
+
+/// A stereo output sample, each side between -1.0 and 1.0.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Sample {
+    pub left: f32,
+    pub right: f32
+}
+
+/// Counts a channel's length down at 256 Hz and turns the channel off when
+/// it runs out, if enabled in NRx4.
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            max,
+            counter: 0,
+            enabled: false
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Loads the length from the value written to NRx1.
+    pub fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    /// Returns true when the counter runs out.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    /// Updates the counter for a write to NRx4 and returns true when that
+    /// turns the channel off. `length_clocked` tells whether the frame
+    /// sequencer's last step clocked the length counters, in which case
+    /// enabling the counter clocks it once more right away.
+    pub fn write_control(&mut self, enable: bool, trigger: bool, length_clocked: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enable;
+
+        let mut expired = false;
+
+        if !was_enabled && enable && length_clocked && self.counter > 0 {
+            self.counter -= 1;
+            expired = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+
+            if enable && length_clocked {
+                self.counter -= 1;
+            }
+        }
+
+        expired
+    }
+
+    /// Clears the counter for NR52 power off. The DMG keeps the count,
+    /// only the CGB clears it.
+    pub fn power_off(&mut self, cgb: bool) {
+        self.enabled = false;
+
+        if cgb {
+            self.counter = 0;
+        }
+    }
+}
+
+/// Steps a channel's volume up or down at 64 Hz, as set in NRx2.
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// The DAC is powered as long as NRx2 doesn't select volume 0 going down.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xf8 != 0
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+
+    pub fn clock(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+
+        // a channel that was never triggered has no timer running yet
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            if self.register & 0x08 != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if self.register & 0x08 == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::new()
+    }
+}
+
+/// Turns a channel's 4 bit output into the DAC's analog level, or silence
+/// when the DAC is off.
+pub fn dac_output(enabled: bool, value: u8) -> f32 {
+    if enabled {
+        value as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+/// A copy of the APU output resampled at a fixed rate, which the host's
+/// rate control leaves alone, and optionally every channel by itself as
+/// they go into the mix, so the stems add up to it.
+pub struct Capture {
+    pub mix: Resampler,
+    pub stems: Option<[Resampler; 4]>
+}
+
+impl Capture {
+    pub fn new(sample_rate: u32, filter: HighPassFilter, stems: bool) -> Capture {
+        Capture {
+            mix: Resampler::new(sample_rate, filter),
+            stems: if stems { Some([(); 4].map(|_| Resampler::new(sample_rate, filter))) } else { None }
+        }
+    }
+
+    fn push(&mut self, sample: Sample, channels: &[Sample; 4]) {
+        self.mix.push(sample);
+
+        if let Some(stems) = self.stems.as_mut() {
+            for (stem, &channel) in stems.iter_mut().zip(channels.iter()) {
+                stem.push(channel);
+            }
+        }
+    }
+}
+
+/// The audio processing unit. It runs at normal speed, so `tick` is given
+/// normal speed cycles, and the frame sequencer steps on the falling edge
+/// of DIV bit 4 (bit 5 in double speed).
+///
+/// | addr  | register                                           |
+/// |-------|----------------------------------------------------|
+/// | FF10  | NR10, channel 1 sweep                              |
+/// | FF11  | NR11, channel 1 duty and length                    |
+/// | FF12  | NR12, channel 1 envelope                           |
+/// | FF13  | NR13, channel 1 frequency bits 0-7                 |
+/// | FF14  | NR14, trigger, length enable and frequency 8-10    |
+/// | FF16  | NR21-NR24, channel 2 like channel 1 without sweep  |
+/// | FF1A  | NR30, channel 3 DAC enable                         |
+/// | FF1B  | NR31, channel 3 length                             |
+/// | FF1C  | NR32, channel 3 output level                       |
+/// | FF1D  | NR33-NR34, channel 3 frequency and control         |
+/// | FF20  | NR41, channel 4 length                             |
+/// | FF21  | NR42, channel 4 envelope                           |
+/// | FF22  | NR43, channel 4 clock shift, width and divisor     |
+/// | FF23  | NR44, channel 4 trigger and length enable          |
+/// | FF24  | NR50, left and right master volume                 |
+/// | FF25  | NR51, which channels go to the left and right      |
+/// | FF26  | NR52, power and the channel status bits            |
+/// | FF30  | wave RAM, up to FF3F                               |
+///
+/// Turning the power off through NR52 clears every register up to NR51 and
+/// ignores writes to them until it is turned back on, except that the DMG
+/// still takes the lengths in NRx1. Wave RAM is left alone.
+///
+/// A stereo sample is produced every M-cycle and resampled for the host,
+/// see `Resampler`.
+pub struct Apu {
+    model: Model,
+    cgb: bool,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+    powered: bool,
+    /// The next frame sequencer step.
+    frame_step: u8,
+    cycles: u32,
+    output: Resampler,
+    capture: Option<Capture>
+}
+
+impl Apu {
+    /// The CGB APU lacks some of the DMG's quirks, and the output's
+    /// high-pass filter depends on the `model` too.
+    pub fn new(model: Model) -> Apu {
+        let cgb = model.is_cgb();
+
+        Apu {
+            model,
+            cgb,
+            pulse1: Pulse::new(true, cgb),
+            pulse2: Pulse::new(false, cgb),
+            wave: Wave::new(cgb),
+            noise: Noise::new(cgb),
+            nr50: 0,
+            nr51: 0,
+            powered: false,
+            frame_step: 0,
+            cycles: 0,
+            output: Resampler::new(SAMPLE_RATE_48000, HighPassFilter::for_model(model)),
+            capture: None
+        }
+    }
+
+    /// Powers everything back on as `new` does, keeping the output and any
+    /// capture along with the samples they hold.
+    pub fn reset(&mut self) {
+        let mut apu = Apu::new(self.model);
+        std::mem::swap(&mut apu.output, &mut self.output);
+        apu.capture = self.capture.take();
+        *self = apu;
+    }
+
+    /// The resampled output the host pulls samples from.
+    pub fn output_mut(&mut self) -> &mut Resampler {
+        &mut self.output
+    }
+
+    /// Starts resampling a second copy of the output at `sample_rate` for
+    /// recording, with `stems` also each channel on its own.
+    pub fn start_capture(&mut self, sample_rate: u32, stems: bool) {
+        self.capture = Some(Capture::new(sample_rate, HighPassFilter::for_model(self.model), stems));
+    }
+
+    pub fn capture_mut(&mut self) -> Option<&mut Capture> {
+        self.capture.as_mut()
+    }
+
+    pub fn stop_capture(&mut self) -> Option<Capture> {
+        self.capture.take()
+    }
+
+    /// Whether the last frame sequencer step clocked the length counters.
+    fn length_clocked(&self) -> bool {
+        self.frame_step & 1 == 1
+    }
+
+    /// Advances the frame sequencer by one step, on a falling edge of DIV.
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        let step = self.frame_step;
+        self.frame_step = (self.frame_step + 1) % FRAME_STEPS;
+
+        if step & 1 == 0 {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+
+        if step == 2 || step == 6 {
+            self.pulse1.clock_sweep();
+        }
+
+        if step == 7 {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+    }
+
+    /// What each channel adds to the mix, panned through NR51 and scaled by
+    /// the master volume.
+    fn channel_samples(&self) -> [Sample; 4] {
+        let outputs = [self.pulse1.output(), self.pulse2.output(), self.wave.output(), self.noise.output()];
+
+        // the mix of all four channels at full master volume spans -1 to 1
+        let left_volume = (((self.nr50 >> 4) & 0x07) as f32 + 1.0) / 32.0;
+        let right_volume = ((self.nr50 & 0x07) as f32 + 1.0) / 32.0;
+
+        let mut samples = [Sample::default(); 4];
+
+        for (channel, (sample, output)) in samples.iter_mut().zip(outputs.iter()).enumerate() {
+            if self.nr51 & (0x10 << channel) != 0 {
+                sample.left = output * left_volume;
+            }
+
+            if self.nr51 & (0x01 << channel) != 0 {
+                sample.right = output * right_volume;
+            }
+        }
+
+        samples
+    }
+
+    /// Advances the channels by `cycles` normal speed cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles;
+
+        while self.cycles >= SAMPLE_CYCLES {
+            self.cycles -= SAMPLE_CYCLES;
+
+            if self.powered {
+                self.pulse1.tick(SAMPLE_CYCLES);
+                self.pulse2.tick(SAMPLE_CYCLES);
+                self.wave.tick(SAMPLE_CYCLES);
+                self.noise.tick(SAMPLE_CYCLES);
+            }
+
+            let channels = self.channel_samples();
+            let sample = channels.iter().fold(Sample::default(), |mix, channel| Sample {
+                left: mix.left + channel.left,
+                right: mix.right + channel.right
+            });
+
+            self.output.push(sample);
+
+            if let Some(capture) = self.capture.as_mut() {
+                capture.push(sample, &channels);
+            }
+        }
+    }
+
+    fn power_off(&mut self) {
+        self.pulse1.power_off();
+        self.pulse2.power_off();
+        self.wave.power_off();
+        self.noise.power_off();
+        self.nr50 = 0;
+        self.nr51 = 0;
+        self.powered = false;
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0xff10..=0xff14 => self.pulse1.read_register(addr - 0xff10),
+            0xff16..=0xff19 => self.pulse2.read_register(addr - 0xff15),
+            0xff1a..=0xff1e => self.wave.read_register(addr - 0xff1a),
+            0xff20..=0xff23 => self.noise.read_register(addr - 0xff1f),
+            0xff24 => self.nr50,
+            0xff25 => self.nr51,
+            0xff26 => {
+                let status = self.pulse1.enabled() as u8
+                    | (self.pulse2.enabled() as u8) << 1
+                    | (self.wave.enabled() as u8) << 2
+                    | (self.noise.enabled() as u8) << 3;
+
+                0x70 | ((self.powered as u8) << 7) | status
+            },
+            0xff30..=0xff3f => self.wave.read_ram((addr - 0xff30) as usize),
+            _ => 0xff
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xff26 => {
+                if value & 0x80 == 0 {
+                    self.power_off();
+                } else if !self.powered {
+                    self.powered = true;
+                    self.frame_step = 0;
+                }
+
+                return;
+            },
+            0xff30..=0xff3f => {
+                self.wave.write_ram((addr - 0xff30) as usize, value);
+                return;
+            },
+            _ => {}
+        }
+
+        if !self.powered {
+            if !self.cgb {
+                match addr {
+                    0xff11 => self.pulse1.load_length(value),
+                    0xff16 => self.pulse2.load_length(value),
+                    0xff1b => self.wave.load_length(value),
+                    0xff20 => self.noise.load_length(value),
+                    _ => {}
+                }
+            }
+
+            return;
+        }
+
+        let length_clocked = self.length_clocked();
+
+        match addr {
+            0xff10..=0xff14 => self.pulse1.write_register(addr - 0xff10, value, length_clocked),
+            0xff16..=0xff19 => self.pulse2.write_register(addr - 0xff15, value, length_clocked),
+            0xff1a..=0xff1e => self.wave.write_register(addr - 0xff1a, value, length_clocked),
+            0xff20..=0xff23 => self.noise.write_register(addr - 0xff1f, value, length_clocked),
+            0xff24 => self.nr50 = value,
+            0xff25 => self.nr51 = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabling_length_after_a_length_step_clocks_it_once_more() {
+        let mut length = LengthCounter::new(64);
+        length.load(60);
+
+        assert!(!length.write_control(true, false, true));
+        assert_eq!(length.counter, 3);
+
+        // not after a step that didn't clock the lengths
+        length.enabled = false;
+        assert!(!length.write_control(true, false, false));
+        assert_eq!(length.counter, 3);
+
+        // and not when it was already enabled
+        assert!(!length.write_control(true, false, true));
+        assert_eq!(length.counter, 3);
+    }
+
+    #[test]
+    fn the_extra_length_clock_can_turn_the_channel_off() {
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+
+        assert!(length.write_control(true, false, true));
+        assert_eq!(length.counter, 0);
+    }
+
+    #[test]
+    fn trigger_reloads_an_expired_length() {
+        let mut length = LengthCounter::new(64);
+        assert!(!length.write_control(false, true, true));
+        assert_eq!(length.counter, 64);
+
+        // the reload is clocked right away too
+        let mut length = LengthCounter::new(64);
+        assert!(!length.write_control(true, true, true));
+        assert_eq!(length.counter, 63);
+
+        // an expiring extra clock is overridden by the trigger
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+        assert!(!length.write_control(true, true, true));
+        assert_eq!(length.counter, 63);
+    }
+
+    /// A powered APU with channel 1 set up to play.
+    fn playing(model: Model) -> Apu {
+        let mut apu = Apu::new(model);
+        apu.write_register(0xff26, 0x80);
+        apu.write_register(0xff11, 0x80);
+        apu.write_register(0xff12, 0xf3);
+        apu.write_register(0xff24, 0x77);
+        apu.write_register(0xff25, 0xf3);
+        apu
+    }
+
+    #[test]
+    fn power_off_clears_the_registers_and_ignores_writes() {
+        let mut apu = playing(Model::Dmg);
+        apu.write_register(0xff30, 0x5a);
+
+        apu.write_register(0xff26, 0x00);
+        assert_eq!(apu.read_register(0xff26), 0x70);
+        assert_eq!(apu.read_register(0xff11), 0x3f);
+        assert_eq!(apu.read_register(0xff12), 0x00);
+        assert_eq!(apu.read_register(0xff24), 0x00);
+        assert_eq!(apu.read_register(0xff25), 0x00);
+
+        apu.write_register(0xff12, 0xf3);
+        apu.write_register(0xff25, 0xff);
+        assert_eq!(apu.read_register(0xff12), 0x00);
+        assert_eq!(apu.read_register(0xff25), 0x00);
+
+        // wave RAM is left alone and stays writable
+        assert_eq!(apu.read_register(0xff30), 0x5a);
+        apu.write_register(0xff31, 0xa5);
+        assert_eq!(apu.read_register(0xff31), 0xa5);
+    }
+
+    /// Loads a length of 1 into channel 1 while powered off, then powers on
+    /// and triggers it with the length enabled. Returns whether it is still
+    /// playing after the next length clock.
+    fn plays_after_a_length_loaded_while_off(model: Model) -> bool {
+        let mut apu = playing(model);
+        apu.write_register(0xff26, 0x00);
+        apu.write_register(0xff11, 0x3f);
+
+        apu.write_register(0xff26, 0x80);
+        apu.write_register(0xff12, 0xf0);
+        apu.write_register(0xff14, 0xc0);
+        assert_eq!(apu.read_register(0xff26) & 0x01, 0x01);
+
+        apu.clock_frame_sequencer();
+        apu.read_register(0xff26) & 0x01 != 0
+    }
+
+    #[test]
+    fn the_dmg_takes_lengths_while_powered_off() {
+        assert!(!plays_after_a_length_loaded_while_off(Model::Dmg));
+    }
+
+    #[test]
+    fn the_cgb_ignores_lengths_while_powered_off() {
+        assert!(plays_after_a_length_loaded_while_off(Model::Cgb));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::apu::{ Envelope, LengthCounter, dac_output };
+
+/// The base periods NR43 bits 0-2 select, before the clock shift.
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// A channel playing pseudo random noise from a linear feedback shift
+/// register. Every period the XOR of its two low bits is shifted in at the
+/// top, bit 14, and with NR43 bit 3 set also at bit 6 for a 7 bit sequence
+/// that sounds more like a tone. The output is high while bit 0 is clear.
+pub struct Noise {
+    cgb: bool,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    /// NR43, clock shift in bits 4-7, width in bit 3 and divisor in bits 0-2.
+    polynomial: u8,
+    lfsr: u16,
+    timer: u32
+}
+
+impl Noise {
+    pub fn new(cgb: bool) -> Noise {
+        Noise {
+            cgb,
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            polynomial: 0,
+            lfsr: 0,
+            timer: 0
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn output(&self) -> f32 {
+        let value = if self.enabled && self.lfsr & 0x01 == 0 { self.envelope.volume() } else { 0 };
+
+        dac_output(self.envelope.dac_enabled(), value)
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[(self.polynomial & 0x07) as usize] << (self.polynomial >> 4)
+    }
+
+    fn clock_lfsr(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+
+        if self.polynomial & 0x08 != 0 {
+            self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        // clock shifts 14 and 15 leave the LFSR without a clock
+        if self.polynomial >> 4 >= 14 {
+            return;
+        }
+
+        let mut cycles = cycles;
+
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.clock_lfsr();
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.lfsr = 0x7fff;
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    /// Reads NR41-NR44 by `index` from 1, write only bits read back set.
+    pub fn read_register(&self, index: u16) -> u8 {
+        match index {
+            2 => self.envelope.read(),
+            3 => self.polynomial,
+            4 => 0xbf | ((self.length.enabled() as u8) << 6),
+            _ => 0xff
+        }
+    }
+
+    /// Writes NR41-NR44 by `index` from 1, see
+    /// `LengthCounter::write_control` for `length_clocked`.
+    pub fn write_register(&mut self, index: u16, value: u8, length_clocked: bool) {
+        match index {
+            1 => self.load_length(value),
+            2 => {
+                self.envelope.write(value);
+
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.polynomial = value,
+            4 => {
+                let trigger = value & 0x80 != 0;
+
+                if self.length.write_control(value & 0x40 != 0, trigger, length_clocked) {
+                    self.enabled = false;
+                }
+
+                if trigger {
+                    self.trigger();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Loads the length from NR41, which the DMG allows while powered off.
+    pub fn load_length(&mut self, value: u8) {
+        self.length.load((value & 0x3f) as u16);
+    }
+
+    /// Clears the channel for NR52 power off, the length counter is up to
+    /// `LengthCounter::power_off`.
+    pub fn power_off(&mut self) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        length.power_off(self.cgb);
+
+        *self = Noise {
+            length,
+            ..Noise::new(self.cgb)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many clocks the LFSR takes to come back to where a trigger
+    /// leaves it, with `polynomial` in NR43.
+    fn sequence_length(polynomial: u8) -> u32 {
+        let mut noise = Noise::new(false);
+        noise.write_register(2, 0xf0, false);
+        noise.write_register(3, polynomial, false);
+        noise.write_register(4, 0x80, false);
+
+        let start = noise.lfsr;
+        let mut clocks = 0;
+
+        loop {
+            noise.clock_lfsr();
+            clocks += 1;
+
+            if noise.lfsr == start || clocks > 0x8000 {
+                return clocks;
+            }
+        }
+    }
+
+    #[test]
+    fn the_lfsr_runs_through_15_bit_sequences() {
+        assert_eq!(sequence_length(0x00), 0x7fff);
+    }
+
+    #[test]
+    fn the_short_width_runs_through_7_bit_sequences() {
+        let mut noise = Noise::new(false);
+        noise.write_register(2, 0xf0, false);
+        noise.write_register(3, 0x08, false);
+        noise.write_register(4, 0x80, false);
+
+        // the low 7 bits repeat every 127 clocks whatever is above them
+        let low_bits: Vec<u16> = (0..254).map(|_| {
+            noise.clock_lfsr();
+            noise.lfsr & 0x7f
+        }).collect();
+
+        assert_eq!(low_bits[..127], low_bits[127..]);
+        assert!(low_bits[..127].iter().any(|&bits| bits != low_bits[0]));
+    }
+
+    #[test]
+    fn the_timer_clocks_the_lfsr_every_shifted_divisor() {
+        let mut noise = Noise::new(false);
+        noise.write_register(2, 0xf0, false);
+        // divisor 16, shifted left by 2
+        noise.write_register(3, 0x21, false);
+        noise.write_register(4, 0x80, false);
+
+        noise.tick(63);
+        assert_eq!(noise.lfsr, 0x7fff);
+
+        // both low bits are set, so a 0 is shifted in
+        noise.tick(1);
+        assert_eq!(noise.lfsr, 0x3fff);
+    }
+}
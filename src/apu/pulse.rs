@@ -0,0 +1,325 @@
+use crate::apu::{ Envelope, LengthCounter, dac_output };
+
+/// The waveforms NRx1 bits 6-7 select between, 12.5%, 25%, 50% and 75%
+/// high, one bit per duty step starting from the most significant.
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Channel 1's frequency sweep, set through NR10.
+struct Sweep {
+    register: u8,
+    enabled: bool,
+    timer: u8,
+    shadow: u16,
+    /// A subtraction has been done since the trigger, which makes clearing
+    /// the negate bit turn the channel off.
+    negated: bool
+}
+
+impl Sweep {
+    fn new() -> Sweep {
+        Sweep {
+            register: 0,
+            enabled: false,
+            timer: 0,
+            shadow: 0,
+            negated: false
+        }
+    }
+
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn negate(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+
+    /// The next frequency, which is past 2047 when the sweep overflows.
+    fn next_frequency(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift();
+
+        if self.negate() {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+/// A square wave channel, channel 1 with a frequency sweep or channel 2
+/// without.
+///
+/// The frequency timer steps through the 8 duty steps every
+/// (2048 - frequency) * 4 cycles.
+pub struct Pulse {
+    cgb: bool,
+    sweep: Option<Sweep>,
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+    frequency: u16,
+    timer: u32
+}
+
+impl Pulse {
+    pub fn new(sweep: bool, cgb: bool) -> Pulse {
+        Pulse {
+            cgb,
+            sweep: if sweep { Some(Sweep::new()) } else { None },
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            frequency: 0,
+            timer: 0
+        }
+    }
+
+    /// Whether the channel is on, as NR52 reports it.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The DAC output, between -1.0 and 1.0.
+    pub fn output(&self) -> f32 {
+        let high = DUTY_PATTERNS[self.duty as usize] & (0x80 >> self.duty_step) != 0;
+        let value = if self.enabled && high { self.envelope.volume() } else { 0 };
+
+        dac_output(self.envelope.dac_enabled(), value)
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let sweep = match self.sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        let frequency = sweep.next_frequency();
+
+        if frequency > 2047 {
+            self.enabled = false;
+            return;
+        }
+
+        if sweep.shift() != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+
+            // the new frequency is checked for overflow straight away
+            if sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow = self.frequency;
+            sweep.negated = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+
+            if sweep.shift() != 0 && sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Reads NRx0-NRx4 by `index`, write only bits read back set.
+    pub fn read_register(&self, index: u16) -> u8 {
+        match index {
+            0 => match self.sweep.as_ref() {
+                Some(sweep) => 0x80 | sweep.register,
+                None => 0xff
+            },
+            1 => (self.duty << 6) | 0x3f,
+            2 => self.envelope.read(),
+            4 => 0xbf | ((self.length.enabled() as u8) << 6),
+            _ => 0xff
+        }
+    }
+
+    /// Writes NRx0-NRx4 by `index`, see `LengthCounter::write_control` for
+    /// `length_clocked`.
+    pub fn write_register(&mut self, index: u16, value: u8, length_clocked: bool) {
+        match index {
+            0 => if let Some(sweep) = self.sweep.as_mut() {
+                let negate = sweep.negate();
+                sweep.register = value & 0x7f;
+
+                if negate && !sweep.negate() && sweep.negated {
+                    self.enabled = false;
+                }
+            },
+            1 => {
+                self.duty = value >> 6;
+                self.load_length(value);
+            },
+            2 => {
+                self.envelope.write(value);
+
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00ff) | (((value & 0x07) as u16) << 8);
+
+                let trigger = value & 0x80 != 0;
+
+                if self.length.write_control(value & 0x40 != 0, trigger, length_clocked) {
+                    self.enabled = false;
+                }
+
+                if trigger {
+                    self.trigger();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Loads the length from NRx1, which the DMG allows while powered off.
+    pub fn load_length(&mut self, value: u8) {
+        self.length.load((value & 0x3f) as u16);
+    }
+
+    /// Clears the channel for NR52 power off, the length counter is up to
+    /// `LengthCounter::power_off`.
+    pub fn power_off(&mut self) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        length.power_off(self.cgb);
+
+        *self = Pulse {
+            length,
+            ..Pulse::new(self.sweep.is_some(), self.cgb)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Channel 1 with its DAC on, about to be triggered at `frequency`
+    /// with `nr10` in the sweep register.
+    fn channel1(nr10: u8, frequency: u16) -> Pulse {
+        let mut pulse = Pulse::new(true, false);
+        pulse.write_register(0, nr10, false);
+        pulse.write_register(2, 0xf0, false);
+        pulse.write_register(3, frequency as u8, false);
+        pulse
+    }
+
+    fn trigger(pulse: &mut Pulse, frequency: u16) {
+        pulse.write_register(4, 0x80 | (frequency >> 8) as u8, false);
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger_disables_the_channel() {
+        // shift 1 adds half of 2047
+        let mut pulse = channel1(0x01, 2047);
+        trigger(&mut pulse, 2047);
+        assert!(!pulse.enabled());
+
+        // shift 0 skips the calculation
+        let mut pulse = channel1(0x00, 2047);
+        trigger(&mut pulse, 2047);
+        assert!(pulse.enabled());
+
+        // and one that doesn't overflow leaves it on
+        let mut pulse = channel1(0x01, 1024);
+        trigger(&mut pulse, 1024);
+        assert!(pulse.enabled());
+    }
+
+    #[test]
+    fn sweep_overflow_on_a_step_disables_the_channel() {
+        // period 1, shift 2: 1600 goes to 2000, whose next step overflows
+        let mut pulse = channel1(0x12, 1600);
+        trigger(&mut pulse, 1600);
+        assert!(pulse.enabled());
+
+        pulse.clock_sweep();
+        assert_eq!(pulse.frequency, 2000);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn clearing_negate_after_a_subtraction_disables_the_channel() {
+        // period 1, negate, shift 1, the trigger already subtracts
+        let mut pulse = channel1(0x19, 1024);
+        trigger(&mut pulse, 1024);
+        assert!(pulse.enabled());
+
+        pulse.write_register(0, 0x11, false);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn clearing_negate_before_any_subtraction_is_harmless() {
+        // negate with shift 0 never calculates
+        let mut pulse = channel1(0x08, 1024);
+        trigger(&mut pulse, 1024);
+
+        pulse.write_register(0, 0x00, false);
+        assert!(pulse.enabled());
+    }
+}
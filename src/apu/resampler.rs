@@ -0,0 +1,382 @@
+use crate::apu::{ Sample, SAMPLE_RATE };
+use crate::model::Model;
+
+use std::f64::consts::PI;
+
+/// Sample rates hosts usually ask for.
+pub const SAMPLE_RATE_44100: u32 = 44_100;
+pub const SAMPLE_RATE_48000: u32 = 48_000;
+
+/// Positions between two output samples a step can be placed at.
+const PHASES: usize = 64;
+
+/// Output samples one step is spread over, the output lags the input by
+/// half of this.
+const TAPS: usize = 16;
+
+/// The low-pass cutoff as a fraction of the output sample rate, a little
+/// below Nyquist so the kernel's roll-off doesn't alias.
+const CUTOFF: f64 = 0.45;
+
+/// Output samples kept for a host that falls behind, older ones are dropped.
+const MAX_BUFFERED_SECONDS: usize = 1;
+
+/// How much of its charge the output capacitor keeps per 4 MHz cycle. The
+/// MGB's and the CGB's drain faster, so their high-pass corner is higher.
+const DMG_CHARGE: f64 = 0.999958;
+const CGB_CHARGE: f64 = 0.998943;
+
+/// The high-pass filter the output capacitor forms, which takes the DC
+/// offset out of the DAC outputs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HighPassFilter {
+    Off,
+    Dmg,
+    Cgb
+}
+
+impl HighPassFilter {
+    /// The filter of `model`'s output stage. The MGB and the SGB2 built on
+    /// it already have the CGB's.
+    pub fn for_model(model: Model) -> HighPassFilter {
+        match model {
+            Model::Dmg0 | Model::Dmg | Model::Sgb => HighPassFilter::Dmg,
+            Model::Mgb | Model::Sgb2 | Model::Cgb | Model::Agb => HighPassFilter::Cgb
+        }
+    }
+
+    /// The share of the capacitor's charge kept from one output sample to
+    /// the next at `sample_rate`.
+    fn charge(self, sample_rate: f64) -> f32 {
+        let charge = match self {
+            HighPassFilter::Off => return 1.0,
+            HighPassFilter::Dmg => DMG_CHARGE,
+            HighPassFilter::Cgb => CGB_CHARGE
+        };
+
+        charge.powf(4_194_304.0 / sample_rate) as f32
+    }
+}
+
+/// Synthesizes one band-limited signal from amplitude steps, the way
+/// blip_buf does. Every step adds a windowed sinc impulse to the buffer at
+/// its exact sub-sample position and reading integrates the impulses back
+/// into steps, so the output carries no frequencies above the cutoff.
+struct BlipBuffer {
+    /// The impulse for each phase, summing to 1.
+    kernel: Vec<[f32; TAPS]>,
+    buffer: Vec<f32>,
+    /// The input's position in output samples from the start of `buffer`.
+    time: f64,
+    /// The running sum of what has been read so far, in f64 so rounding
+    /// doesn't build up into an offset over a long run.
+    integrator: f64
+}
+
+impl BlipBuffer {
+    fn new() -> BlipBuffer {
+        let kernel = (0..=PHASES).map(|phase| {
+            let offset = phase as f64 / PHASES as f64;
+            let mut row = [0.0; TAPS];
+
+            for (i, tap) in row.iter_mut().enumerate() {
+                let x = i as f64 - offset - (TAPS / 2) as f64 + 1.0;
+
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x)
+                };
+
+                // a Blackman window across the taps
+                let w = 2.0 * PI * (x / TAPS as f64 + 0.5);
+                let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+
+                *tap = sinc * window;
+            }
+
+            let sum: f64 = row.iter().sum();
+            let mut normalized = [0.0; TAPS];
+
+            for (tap, &value) in normalized.iter_mut().zip(row.iter()) {
+                *tap = (value / sum) as f32;
+            }
+
+            normalized
+        }).collect();
+
+        BlipBuffer {
+            kernel,
+            buffer: vec![0.0; TAPS],
+            time: 0.0,
+            integrator: 0.0
+        }
+    }
+
+    /// Output samples that no later step can change any more.
+    fn available(&self) -> usize {
+        self.time as usize
+    }
+
+    /// Adds a step of `delta` at the current time.
+    fn add_delta(&mut self, delta: f32) {
+        let position = self.time as usize;
+        let phase = ((self.time - position as f64) * PHASES as f64).round() as usize;
+
+        for (sample, &tap) in self.buffer[position..position + TAPS].iter_mut().zip(self.kernel[phase].iter()) {
+            *sample += delta * tap;
+        }
+    }
+
+    fn advance(&mut self, samples: f64) {
+        self.time += samples;
+
+        // room for a step at the new time
+        let end = self.time as usize + TAPS;
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0.0);
+        }
+    }
+
+    /// Integrates the output sample at `index`, reading them in order.
+    fn read_sample(&mut self, index: usize) -> f32 {
+        self.integrator += self.buffer[index] as f64;
+        self.integrator as f32
+    }
+
+    /// Drops the first `count` samples, after reading or discarding them.
+    fn remove(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.time -= count as f64;
+
+        if self.buffer.len() < TAPS {
+            self.buffer.resize(TAPS, 0.0);
+        }
+    }
+
+    /// Throws away the first `count` samples, keeping the output level.
+    fn skip(&mut self, count: usize) {
+        self.integrator += self.buffer[..count].iter().map(|&sample| sample as f64).sum::<f64>();
+        self.remove(count);
+    }
+}
+
+/// Turns the APU's ~1 MHz samples into stereo at a host sample rate, with
+/// band-limited synthesis so nothing aliases, and runs them through the
+/// output high-pass filter.
+///
+/// The host pulls samples out with `read_f32` or `read_i16` whenever its
+/// audio buffer needs filling. Samples it doesn't pull within a second are
+/// dropped.
+pub struct Resampler {
+    left: BlipBuffer,
+    right: BlipBuffer,
+    last: Sample,
+    sample_rate: f64,
+    /// Output samples per input sample.
+    ratio: f64,
+    filter: HighPassFilter,
+    charge: f32,
+    capacitor: (f32, f32)
+}
+
+impl Resampler {
+    pub fn new(sample_rate: u32, filter: HighPassFilter) -> Resampler {
+        let mut resampler = Resampler {
+            left: BlipBuffer::new(),
+            right: BlipBuffer::new(),
+            last: Sample::default(),
+            sample_rate: 0.0,
+            ratio: 0.0,
+            filter,
+            charge: 1.0,
+            capacitor: (0.0, 0.0)
+        };
+
+        resampler.set_sample_rate(sample_rate as f64);
+        resampler
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Changes the output rate. It doesn't need to be whole, so a host can
+    /// nudge it to keep up with its audio device.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.ratio = sample_rate / SAMPLE_RATE as f64;
+        self.charge = self.filter.charge(sample_rate);
+    }
+
+    pub fn set_filter(&mut self, filter: HighPassFilter) {
+        self.filter = filter;
+        self.charge = filter.charge(self.sample_rate);
+    }
+
+    /// Adds the next APU sample.
+    pub fn push(&mut self, sample: Sample) {
+        if sample.left != self.last.left {
+            self.left.add_delta(sample.left - self.last.left);
+        }
+
+        if sample.right != self.last.right {
+            self.right.add_delta(sample.right - self.last.right);
+        }
+
+        self.last = sample;
+
+        self.left.advance(self.ratio);
+        self.right.advance(self.ratio);
+
+        // drop half the backlog at once rather than a sample at a time
+        let limit = self.sample_rate as usize * MAX_BUFFERED_SECONDS;
+        let available = self.available();
+
+        if available > limit {
+            self.left.skip(available - limit / 2);
+            self.right.skip(available - limit / 2);
+        }
+    }
+
+    /// Stereo samples ready to be read.
+    pub fn available(&self) -> usize {
+        self.left.available().min(self.right.available())
+    }
+
+    fn read<F: FnMut(usize, f32, f32)>(&mut self, frames: usize, mut write: F) -> usize {
+        let frames = frames.min(self.available());
+
+        for frame in 0..frames {
+            let left = self.left.read_sample(frame);
+            let right = self.right.read_sample(frame);
+
+            // the capacitor charges towards the signal and the output is what's left
+            let out_left = left - self.capacitor.0;
+            let out_right = right - self.capacitor.1;
+            self.capacitor.0 = left - out_left * self.charge;
+            self.capacitor.1 = right - out_right * self.charge;
+
+            write(frame, out_left, out_right);
+        }
+
+        self.left.remove(frames);
+        self.right.remove(frames);
+        frames
+    }
+
+    /// Fills `out` with interleaved left and right samples between -1.0 and
+    /// 1.0 as far as there are samples, returning how many stereo samples
+    /// were written.
+    pub fn read_f32(&mut self, out: &mut [f32]) -> usize {
+        self.read(out.len() / 2, |frame, left, right| {
+            out[frame * 2] = left;
+            out[frame * 2 + 1] = right;
+        })
+    }
+
+    /// Like `read_f32`, with 16 bit samples.
+    pub fn read_i16(&mut self, out: &mut [i16]) -> usize {
+        let to_i16 = |value: f32| (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+
+        self.read(out.len() / 2, |frame, left, right| {
+            out[frame * 2] = to_i16(left);
+            out[frame * 2 + 1] = to_i16(right);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(value: f32) -> Sample {
+        Sample { left: value, right: -value }
+    }
+
+    /// Reads everything available, returning the last stereo sample.
+    fn read_all(resampler: &mut Resampler) -> (f32, f32) {
+        let mut out = vec![0.0; resampler.available() * 2];
+        let count = resampler.read_f32(&mut out);
+        assert!(count > 0);
+
+        (out[count * 2 - 2], out[count * 2 - 1])
+    }
+
+    #[test]
+    fn a_step_settles_at_its_level_without_the_filter() {
+        let mut resampler = Resampler::new(SAMPLE_RATE_48000, HighPassFilter::Off);
+
+        for _ in 0..SAMPLE_RATE / 100 {
+            resampler.push(level(0.5));
+        }
+
+        let (left, right) = read_all(&mut resampler);
+        assert!((left - 0.5).abs() < 1e-6, "{}", left);
+        assert!((right + 0.5).abs() < 1e-6, "{}", right);
+    }
+
+    #[test]
+    fn the_filter_takes_out_a_constant_level() {
+        let mut resampler = Resampler::new(SAMPLE_RATE_48000, HighPassFilter::Cgb);
+
+        for _ in 0..SAMPLE_RATE / 2 {
+            resampler.push(level(0.5));
+        }
+
+        let (left, _) = read_all(&mut resampler);
+        assert!(left.abs() < 1e-3, "{}", left);
+    }
+
+    #[test]
+    fn trimming_the_backlog_keeps_the_level() {
+        let mut resampler = Resampler::new(SAMPLE_RATE_48000, HighPassFilter::Off);
+
+        for _ in 0..SAMPLE_RATE * 2 {
+            resampler.push(level(0.5));
+        }
+
+        assert!(resampler.available() <= SAMPLE_RATE_48000 as usize);
+
+        let mut out = vec![0.0; resampler.available() * 2];
+        let count = resampler.read_f32(&mut out);
+        assert!(out[..count * 2].chunks(2).all(|frame| (frame[0] - 0.5).abs() < 1e-6 && (frame[1] + 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn many_steps_settle_back_to_silence_without_the_filter() {
+        let mut resampler = Resampler::new(SAMPLE_RATE_48000, HighPassFilter::Off);
+        let mut out = vec![0.0; 4096];
+
+        // a 1 kHz square wave for ten seconds, read as it goes
+        for _ in 0..10_000 {
+            for value in [0.9, 0.0] {
+                for _ in 0..SAMPLE_RATE / 2000 {
+                    resampler.push(level(value));
+                }
+            }
+
+            resampler.read_f32(&mut out);
+        }
+
+        for _ in 0..SAMPLE_RATE / 100 {
+            resampler.push(level(0.0));
+        }
+
+        let (left, right) = read_all(&mut resampler);
+        assert!(left.abs() < 1e-6 && right.abs() < 1e-6, "{} {}", left, right);
+    }
+
+    #[test]
+    fn i16_output_is_clamped() {
+        let mut resampler = Resampler::new(SAMPLE_RATE_48000, HighPassFilter::Off);
+
+        for _ in 0..SAMPLE_RATE / 100 {
+            resampler.push(level(1.5));
+        }
+
+        let mut out = vec![0; resampler.available() * 2];
+        let count = resampler.read_i16(&mut out);
+        assert_eq!(out[count * 2 - 2..count * 2], [i16::MAX, -i16::MAX]);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::apu::{ LengthCounter, dac_output };
+
+pub const WAVE_RAM_SIZE: usize = 0x10;
+
+/// A channel playing back the 32 4 bit samples in wave RAM, high nibble
+/// first, one sample every (2048 - frequency) * 2 cycles.
+///
+/// While the channel plays, wave RAM accesses go to the byte it is reading.
+/// The DMG only lets them through right as the channel reads it, other
+/// times reads give 0xFF and writes are lost, and retriggering it just as
+/// it reads scribbles over the first bytes of wave RAM.
+pub struct Wave {
+    cgb: bool,
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    /// NR32 bits 5-6, 0 mutes, 1-3 shift the samples right by 0-2.
+    volume: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample: u8,
+    ram: [u8; WAVE_RAM_SIZE],
+    /// The channel read wave RAM during the last M-cycle.
+    just_read: bool
+}
+
+impl Wave {
+    pub fn new(cgb: bool) -> Wave {
+        Wave {
+            cgb,
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; WAVE_RAM_SIZE],
+            just_read: false
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn output(&self) -> f32 {
+        let value = if self.enabled && self.volume != 0 { self.sample >> (self.volume - 1) } else { 0 };
+
+        dac_output(self.dac_enabled, value)
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.just_read = false;
+
+        if !self.enabled {
+            return;
+        }
+
+        let mut cycles = cycles;
+
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0f };
+            self.just_read = true;
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn trigger(&mut self) {
+        // on the DMG a retrigger right as the next byte is read corrupts wave RAM
+        if !self.cgb && self.enabled && self.timer <= 2 {
+            let index = ((self.position as usize + 1) % 32) / 2;
+
+            if index < 4 {
+                self.ram[0] = self.ram[index];
+            } else {
+                let block = index & !0x03;
+                self.ram.copy_within(block..block + 4, 0);
+            }
+        }
+
+        self.enabled = self.dac_enabled;
+        self.position = 0;
+        // the first sample is read a little late
+        self.timer = self.period() + 6;
+    }
+
+    /// The wave RAM byte the CPU reaches at `index`, if any.
+    fn ram_index(&self, index: usize) -> Option<usize> {
+        if !self.enabled {
+            Some(index)
+        } else if self.cgb || self.just_read {
+            Some(self.position as usize / 2)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_ram(&self, index: usize) -> u8 {
+        self.ram_index(index).map_or(0xff, |index| self.ram[index])
+    }
+
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        if let Some(index) = self.ram_index(index) {
+            self.ram[index] = value;
+        }
+    }
+
+    /// Reads NR30-NR34 by `index`, write only bits read back set.
+    pub fn read_register(&self, index: u16) -> u8 {
+        match index {
+            0 => 0x7f | ((self.dac_enabled as u8) << 7),
+            2 => 0x9f | (self.volume << 5),
+            4 => 0xbf | ((self.length.enabled() as u8) << 6),
+            _ => 0xff
+        }
+    }
+
+    /// Writes NR30-NR34 by `index`, see `LengthCounter::write_control` for
+    /// `length_clocked`.
+    pub fn write_register(&mut self, index: u16, value: u8, length_clocked: bool) {
+        match index {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            },
+            1 => self.load_length(value),
+            2 => self.volume = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00ff) | (((value & 0x07) as u16) << 8);
+
+                let trigger = value & 0x80 != 0;
+
+                if self.length.write_control(value & 0x40 != 0, trigger, length_clocked) {
+                    self.enabled = false;
+                }
+
+                if trigger {
+                    self.trigger();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Loads the length from NR31, which the DMG allows while powered off.
+    pub fn load_length(&mut self, value: u8) {
+        self.length.load(value as u16);
+    }
+
+    /// Clears the channel for NR52 power off. Wave RAM is left alone and
+    /// the length counter is up to `LengthCounter::power_off`.
+    pub fn power_off(&mut self) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(256));
+        length.power_off(self.cgb);
+
+        *self = Wave {
+            length,
+            ram: self.ram,
+            ..Wave::new(self.cgb)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A channel playing a sample every 4 cycles from wave RAM holding
+    /// 0x00, 0x11 up to 0xFF, triggered and then run until the DMG is
+    /// about to read the byte after sample `position`.
+    fn playing(cgb: bool, position: u8) -> Wave {
+        let mut wave = Wave::new(cgb);
+
+        for index in 0..WAVE_RAM_SIZE {
+            wave.write_ram(index, index as u8 * 0x11);
+        }
+
+        wave.write_register(0, 0x80, false);
+        wave.write_register(3, 0xfe, false);
+        wave.write_register(4, 0x87, false);
+
+        // the first sample comes 6 cycles late
+        wave.tick(10);
+        wave.tick(4 * (position as u32 - 1));
+        wave.tick(2);
+        assert_eq!(wave.position, position);
+
+        wave
+    }
+
+    fn retrigger(wave: &mut Wave) {
+        wave.write_register(4, 0x87, false);
+    }
+
+    #[test]
+    fn dmg_retrigger_in_the_first_bytes_copies_one_byte() {
+        let mut wave = playing(false, 3);
+        retrigger(&mut wave);
+
+        assert_eq!(wave.ram[..4], [0x22, 0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn dmg_retrigger_further_in_copies_the_block_of_four() {
+        let mut wave = playing(false, 9);
+        retrigger(&mut wave);
+
+        assert_eq!(wave.ram[..4], [0x44, 0x55, 0x66, 0x77]);
+        assert_eq!(wave.ram[4..8], [0x44, 0x55, 0x66, 0x77]);
+    }
+
+    #[test]
+    fn retrigger_away_from_a_read_leaves_wave_ram_alone() {
+        let mut wave = playing(false, 9);
+        wave.timer += 1;
+        retrigger(&mut wave);
+
+        assert_eq!(wave.ram[..4], [0x00, 0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn cgb_retrigger_leaves_wave_ram_alone() {
+        let mut wave = playing(true, 9);
+        retrigger(&mut wave);
+
+        assert_eq!(wave.ram[..4], [0x00, 0x11, 0x22, 0x33]);
+    }
+}
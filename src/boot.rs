@@ -0,0 +1,79 @@
+use crate::model::Model;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// DMG, MGB and SGB boot ROMs cover 0x0000-0x00FF.
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+
+/// CGB boot ROMs also cover 0x0200-0x08FF, the cartridge header stays
+/// visible in the gap between the two halves.
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+#[derive(Debug)]
+pub enum BootRomError {
+    Io(io::Error),
+    /// Neither the size of a DMG nor a CGB boot ROM.
+    BadSize(usize),
+    /// A DMG boot ROM for a CGB or the other way around.
+    WrongModel(Model)
+}
+
+impl fmt::Display for BootRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootRomError::Io(err) => write!(f, "could not read boot ROM: {}", err),
+            BootRomError::BadSize(len) => write!(f,
+                "boot ROM is {} bytes, expected {} or {}", len, DMG_BOOT_ROM_SIZE, CGB_BOOT_ROM_SIZE),
+            BootRomError::WrongModel(model) => write!(f, "boot ROM does not fit a {}", model),
+        }
+    }
+}
+
+impl std::error::Error for BootRomError {}
+
+impl From<io::Error> for BootRomError {
+    fn from(err: io::Error) -> Self {
+        BootRomError::Io(err)
+    }
+}
+
+/// A boot ROM image, mapped over the start of the cartridge until the
+/// program it contains writes to 0xFF50.
+#[derive(Debug, Clone)]
+pub struct BootRom {
+    data: Vec<u8>
+}
+
+impl BootRom {
+    pub fn new(data: Vec<u8>) -> Result<BootRom, BootRomError> {
+        match data.len() {
+            DMG_BOOT_ROM_SIZE | CGB_BOOT_ROM_SIZE => Ok(BootRom { data }),
+            len => Err(BootRomError::BadSize(len))
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BootRom, BootRomError> {
+        BootRom::new(fs::read(path)?)
+    }
+
+    /// Loads the conventionally named boot ROM for `model` from `dir`.
+    pub fn for_model<P: AsRef<Path>>(dir: P, model: Model) -> Result<BootRom, BootRomError> {
+        BootRom::load(dir.as_ref().join(model.boot_rom_name()))
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.data.len() == CGB_BOOT_ROM_SIZE
+    }
+
+    /// The byte at `addr` if the boot ROM covers it.
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x0000..=0x00ff => Some(self.data[addr as usize]),
+            0x0200..=0x08ff if self.is_cgb() => Some(self.data[addr as usize]),
+            _ => None
+        }
+    }
+}
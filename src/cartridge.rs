@@ -0,0 +1,302 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub mod camera;
+pub mod header;
+pub mod huc1;
+pub mod huc3;
+pub mod mbc;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc6;
+pub mod mbc7;
+pub mod mmm01;
+
+use camera::{ Camera, ImageSource };
+use header::{ Header, Mapper };
+use huc1::HuC1;
+use huc3::HuC3;
+use mbc::{ Mbc, RomOnly };
+use mbc1::Mbc1;
+use mbc2::{ Mbc2, MBC2_RAM_SIZE };
+use mbc3::Mbc3;
+use mbc5::Mbc5;
+use mbc6::Mbc6;
+use mbc7::{ Mbc7, TiltSource, MBC7_EEPROM_SIZE };
+use mmm01::Mmm01;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    /// The image ends before the header does.
+    Truncated { len: usize },
+    UnknownCartridgeType(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    UnsupportedMapper(Mapper),
+    BadLogo,
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+    /// The image is not the size the header declares.
+    RomSizeMismatch { declared: usize, actual: usize }
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(err) => write!(f, "could not read cartridge: {}", err),
+            CartridgeError::Truncated { len } =>
+                write!(f, "image is {} bytes, too short to hold a header", len),
+            CartridgeError::UnknownCartridgeType(code) => write!(f, "unknown cartridge type {:#04x}", code),
+            CartridgeError::UnknownRomSize(code) => write!(f, "unknown ROM size code {:#04x}", code),
+            CartridgeError::UnknownRamSize(code) => write!(f, "unknown RAM size code {:#04x}", code),
+            CartridgeError::UnsupportedMapper(mapper) => write!(f, "{:?} cartridges are not supported", mapper),
+            CartridgeError::BadLogo => write!(f, "Nintendo logo does not match"),
+            CartridgeError::HeaderChecksum { expected, actual } =>
+                write!(f, "header checksum is {:#04x}, computed {:#04x}", expected, actual),
+            CartridgeError::GlobalChecksum { expected, actual } =>
+                write!(f, "global checksum is {:#06x}, computed {:#06x}", expected, actual),
+            CartridgeError::RomSizeMismatch { declared, actual } =>
+                write!(f, "header declares {} bytes of ROM but the image is {} bytes", declared, actual),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(err: io::Error) -> Self {
+        CartridgeError::Io(err)
+    }
+}
+
+/// A cartridge image along with its decoded header, external RAM and the
+/// mapper that banks them in.
+pub struct Cartridge {
+    header: Header,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Box<dyn Mbc>,
+    /// Something in the save data changed since the last `take_dirty`.
+    dirty: bool
+}
+
+impl Cartridge {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Cartridge, CartridgeError> {
+        Cartridge::from_bytes(fs::read(path)?)
+    }
+
+    /// Parses the header of an image. Only what is needed to map the
+    /// cartridge is checked here, `validate` does the rest.
+    pub fn from_bytes(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = Header::parse(&rom)?;
+        // MBC2 and MBC7 keep their storage outside the usual RAM chip and
+        // declare no RAM in the header
+        let ram = match header.cartridge_type.mapper {
+            Mapper::Mbc2 => vec![0; MBC2_RAM_SIZE],
+            Mapper::Mbc7 => vec![0xff; MBC7_EEPROM_SIZE],
+            _ => vec![0; header.ram_size()]
+        };
+
+        let mbc: Box<dyn Mbc> = match header.cartridge_type.mapper {
+            Mapper::RomOnly => Box::new(RomOnly),
+            Mapper::Mbc1 => Box::new(Mbc1::new(&rom)),
+            Mapper::Mbc2 => Box::new(Mbc2::new()),
+            Mapper::Mmm01 => Box::new(Mmm01::new()),
+            Mapper::Mbc3 => Box::new(Mbc3::new(header.cartridge_type.timer)),
+            Mapper::Mbc5 => Box::new(Mbc5::new(header.cartridge_type.rumble)),
+            Mapper::Mbc6 => Box::new(Mbc6::new()),
+            Mapper::Mbc7 => Box::new(Mbc7::new()),
+            Mapper::PocketCamera => Box::new(Camera::new()),
+            Mapper::HuC3 => Box::new(HuC3::new()),
+            Mapper::HuC1 => Box::new(HuC1::new()),
+            mapper => return Err(CartridgeError::UnsupportedMapper(mapper))
+        };
+
+        Ok(Cartridge { header, rom, ram, mbc, dirty: false })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Checks the logo and header checksum the boot ROM verifies, and that
+    /// the image is as large as the header claims.
+    pub fn validate(&self) -> Result<(), CartridgeError> {
+        if !self.header.logo_matches() {
+            return Err(CartridgeError::BadLogo);
+        }
+
+        let checksum = Header::compute_header_checksum(&self.rom);
+        if checksum != self.header.header_checksum {
+            return Err(CartridgeError::HeaderChecksum { expected: self.header.header_checksum, actual: checksum });
+        }
+
+        if self.rom.len() != self.header.rom_size() {
+            return Err(CartridgeError::RomSizeMismatch { declared: self.header.rom_size(), actual: self.rom.len() });
+        }
+
+        Ok(())
+    }
+
+    /// Checked separately from `validate` as plenty of homebrew and test
+    /// ROMs never fill it in.
+    pub fn verify_global_checksum(&self) -> Result<(), CartridgeError> {
+        let checksum = Header::compute_global_checksum(&self.rom);
+
+        if checksum != self.header.global_checksum {
+            return Err(CartridgeError::GlobalChecksum { expected: self.header.global_checksum, actual: checksum });
+        }
+
+        Ok(())
+    }
+
+    pub fn read_rom(&self, addr: u16) -> u8 {
+        self.mbc.read_rom(&self.rom, addr)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        if self.mbc.write_rom(addr, value) {
+            self.dirty = true;
+        }
+    }
+
+    pub fn read_ram(&self, addr: u16) -> u8 {
+        self.mbc.read_ram(&self.ram, addr)
+    }
+
+    pub fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.mbc.write_ram(&mut self.ram, addr, value) {
+            self.dirty = true;
+        }
+    }
+
+    /// Whether the RAM keeps its contents with the power off.
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.battery
+    }
+
+    /// True if anything in `save_data` changed since the last call, the
+    /// RAM or mapper state such as flash or the clock registers.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    /// Resets the mapper's registers, keeping the RAM and anything else
+    /// that is saved.
+    pub fn reset(&mut self) {
+        self.mbc.reset();
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.mbc.tick(cycles);
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.mbc.rumble()
+    }
+
+    /// Drives the MBC7 accelerometer, ignored by other mappers.
+    pub fn set_tilt_source(&mut self, source: TiltSource) {
+        self.mbc.set_tilt_source(source);
+    }
+
+    /// Drives the Game Boy Camera sensor, ignored by other mappers.
+    pub fn set_image_source(&mut self, source: ImageSource) {
+        self.mbc.set_image_source(source);
+    }
+
+    /// The contents of a save file: the external RAM followed by whatever
+    /// state the mapper keeps, e.g. the MBC3 clock trailer.
+    pub fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        data.extend(self.mbc.save_state());
+        data
+    }
+
+    /// Restores a save file written by `save_data` or another emulator
+    /// using the same layout. A short file only fills the start of the RAM.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+
+        if data.len() > self.ram.len() {
+            self.mbc.load_state(&data[self.ram.len()..]);
+        }
+    }
+}
+
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,
+            "{} ({:?}, {} KiB ROM, {} KiB RAM, v{})",
+            self.header.title, self.header.cartridge_type.mapper,
+            self.header.rom_size() / 1024, self.header.ram_size() / 1024, self.header.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use header::test_image;
+
+    fn cartridge(cartridge_type: u8, ram_size: u8) -> Cartridge {
+        Cartridge::from_bytes(test_image(b"DIRTY", cartridge_type, 0x00, ram_size)).unwrap()
+    }
+
+    #[test]
+    fn only_stored_writes_make_the_save_dirty() {
+        let mut cartridge = cartridge(0x03, 0x02);
+        assert!(!cartridge.take_dirty());
+
+        // RAM disabled
+        cartridge.write_ram(0xa000, 0x42);
+        assert!(!cartridge.take_dirty());
+
+        cartridge.write_rom(0x2000, 0x02);
+        assert!(!cartridge.take_dirty());
+
+        cartridge.write_rom(0x0000, 0x0a);
+        cartridge.write_ram(0xa000, 0x42);
+        assert!(cartridge.take_dirty());
+        assert!(!cartridge.take_dirty());
+        assert_eq!(cartridge.save_data()[0], 0x42);
+    }
+
+    #[test]
+    fn writes_without_ram_stay_clean() {
+        let mut cartridge = cartridge(0x00, 0x00);
+
+        cartridge.write_ram(0xa000, 0x42);
+        assert!(!cartridge.take_dirty());
+    }
+
+    #[test]
+    fn clock_and_flash_writes_make_the_save_dirty() {
+        let mut mbc3 = cartridge(0x10, 0x03);
+        mbc3.write_rom(0x0000, 0x0a);
+        mbc3.write_rom(0x4000, 0x08);
+        mbc3.write_ram(0xa000, 0x12);
+        assert!(mbc3.take_dirty());
+
+        let mut mbc6 = cartridge(0x20, 0x03);
+        mbc6.write_rom(0x0c00, 0x01);
+        mbc6.write_rom(0x1000, 0x01);
+        mbc6.write_rom(0x2800, 0x08);
+        // unlock then program through the 0x4000 window
+        for (bank, addr, value) in [(2, 0x5555, 0xaa), (1, 0x4aaa, 0x55), (2, 0x5555, 0xa0), (0, 0x4000, 0x00)] {
+            mbc6.write_rom(0x2000, bank);
+            assert!(!mbc6.take_dirty());
+            mbc6.write_rom(addr, value);
+        }
+        assert!(mbc6.take_dirty());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte, ram_index, store_ram };
+
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+/// Fills a CAMERA_WIDTH x CAMERA_HEIGHT buffer with the sensor image, one
+/// byte per pixel from 0x00 (black) to 0xFF (white), row by row.
+pub type ImageSource = Box<dyn FnMut(&mut [u8])>;
+
+/// Captured pictures land in RAM bank 0 as 16 x 14 tiles.
+const IMAGE_OFFSET: usize = 0x0100;
+
+const REG_CONTROL: usize = 0x00;
+const REG_EXPOSURE_HIGH: usize = 0x02;
+const REG_EXPOSURE_LOW: usize = 0x03;
+const REG_EDGE: usize = 0x04;
+const REG_MATRIX: usize = 0x06;
+const REGISTER_COUNT: usize = 0x36;
+
+/// The Game Boy Camera (Pocket Camera) mapper and its M64282FP sensor.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | RAM write enable, 0x0A enables                  |
+/// | 2000-3FFF   | six bit ROM bank                                |
+/// | 4000-5FFF   | RAM bank 0-F, bit 4 maps the sensor registers   |
+///
+/// Of the sensor registers only 0xA000 can be read back, bit 0 starts a
+/// capture and stays set while it is in progress. 0xA002-0xA003 hold the
+/// exposure time and 0xA006-0xA035 the 4x4 dithering matrix with three
+/// thresholds per entry.
+pub struct Camera {
+    ram_write_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers_mapped: bool,
+    registers: [u8; REGISTER_COUNT],
+    busy_cycles: u32,
+    image_source: Option<ImageSource>
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            ram_write_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_mapped: false,
+            registers: [0; REGISTER_COUNT],
+            busy_cycles: 0,
+            image_source: None
+        }
+    }
+
+    fn exposure(&self) -> u32 {
+        ((self.registers[REG_EXPOSURE_HIGH] as u32) << 8) | self.registers[REG_EXPOSURE_LOW] as u32
+    }
+
+    /// Takes a picture and writes it out as 2bpp tiles. The result is
+    /// available straight away, the busy flag only models how long games
+    /// expect to wait for it.
+    fn capture(&mut self, ram: &mut [u8]) {
+        let mut image = vec![0x80; CAMERA_WIDTH * CAMERA_HEIGHT];
+        if let Some(source) = self.image_source.as_mut() {
+            source(&mut image);
+        }
+
+        let exposure = self.exposure().max(1);
+        let invert = self.registers[REG_EDGE] & 0x08 != 0;
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let mut value = ((image[y * CAMERA_WIDTH + x] as u32 * exposure) / 0x0800).min(0xff) as u8;
+                if invert {
+                    value = !value;
+                }
+
+                let matrix = REG_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+
+                let color = match value {
+                    v if v < thresholds[0] => 3,
+                    v if v < thresholds[1] => 2,
+                    v if v < thresholds[2] => 1,
+                    _ => 0
+                };
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let row = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+
+                if row + 1 >= ram.len() {
+                    continue;
+                }
+
+                ram[row] = if color & 0x01 != 0 { ram[row] | bit } else { ram[row] & !bit };
+                ram[row + 1] = if color & 0x02 != 0 { ram[row + 1] | bit } else { ram[row + 1] & !bit };
+            }
+        }
+
+        self.busy_cycles = (32446 + 16 * self.exposure()) * 4;
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new()
+    }
+}
+
+impl Mbc for Camera {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ram_write_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = value & 0x3f,
+            0x4000..=0x5fff => {
+                self.registers_mapped = value & 0x10 != 0;
+                self.ram_bank = value & 0x0f;
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if self.registers_mapped {
+            return match (addr & 0x7f) as usize {
+                REG_CONTROL => (self.registers[REG_CONTROL] & 0x06) | (self.busy_cycles > 0) as u8,
+                _ => 0x00
+            };
+        }
+
+        ram_index(ram, self.ram_bank as usize, addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if self.registers_mapped {
+            let register = (addr & 0x7f) as usize;
+
+            if register < REGISTER_COUNT {
+                self.registers[register] = value;
+            }
+
+            if register == REG_CONTROL && value & 0x01 != 0 && self.busy_cycles == 0 {
+                self.capture(ram);
+                return true;
+            }
+
+            return false;
+        }
+
+        if !self.ram_write_enabled {
+            return false;
+        }
+
+        store_ram(ram, self.ram_bank as usize, addr, value)
+    }
+
+    fn reset(&mut self) {
+        *self = Camera { image_source: self.image_source.take(), ..Camera::new() };
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+    }
+
+    fn set_image_source(&mut self, source: ImageSource) {
+        self.image_source = Some(source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::CLOCK_SPEED;
+
+    fn map_registers(camera: &mut Camera) {
+        camera.write_rom(0x4000, 0x10);
+    }
+
+    #[test]
+    fn captures_into_ram() {
+        let mut ram = vec![0; 0x20000];
+        let mut camera = Camera::new();
+
+        // black on the left of every tile, white on the right
+        camera.set_image_source(Box::new(|image: &mut [u8]| {
+            for (i, pixel) in image.iter_mut().enumerate() {
+                *pixel = if i % 8 < 4 { 0x00 } else { 0xff };
+            }
+        }));
+
+        map_registers(&mut camera);
+        camera.write_ram(&mut ram, 0xa002, 0x08);
+        camera.write_ram(&mut ram, 0xa003, 0x00);
+        for entry in 0..16 {
+            let matrix = 0xa006 + entry * 3;
+            camera.write_ram(&mut ram, matrix, 0x40);
+            camera.write_ram(&mut ram, matrix + 1, 0x80);
+            camera.write_ram(&mut ram, matrix + 2, 0xc0);
+        }
+
+        camera.write_ram(&mut ram, 0xa000, 0x01);
+        assert_eq!(camera.read_ram(&ram, 0xa000) & 0x01, 0x01);
+
+        camera.tick(CLOCK_SPEED);
+        assert_eq!(camera.read_ram(&ram, 0xa000) & 0x01, 0x00);
+
+        let last_row = IMAGE_OFFSET + (CAMERA_WIDTH / 8) * (CAMERA_HEIGHT / 8) * 16 - 2;
+        assert_eq!(ram[IMAGE_OFFSET..IMAGE_OFFSET + 2], [0xf0, 0xf0]);
+        assert_eq!(ram[last_row..last_row + 2], [0xf0, 0xf0]);
+        assert_eq!(ram[IMAGE_OFFSET - 1], 0x00);
+
+        camera.write_rom(0x4000, 0x00);
+        assert_eq!(camera.read_ram(&ram, 0xa100), 0xf0);
+    }
+
+    #[test]
+    fn ram_writes_need_the_enable() {
+        let mut ram = vec![0; 0x20000];
+        let mut camera = Camera::new();
+
+        camera.write_rom(0x4000, 0x03);
+        camera.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[3 * 0x2000], 0x00);
+
+        camera.write_rom(0x0000, 0x0a);
+        camera.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[3 * 0x2000], 0x42);
+        assert_eq!(camera.read_ram(&ram, 0xa000), 0x42);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::cartridge::CartridgeError;
+
+pub const HEADER_START: usize = 0x0100;
+pub const HEADER_END: usize = 0x0150;
+
+const LOGO_ADDR: usize = 0x0104;
+const TITLE_ADDR: usize = 0x0134;
+const MANUFACTURER_ADDR: usize = 0x013f;
+const CGB_FLAG_ADDR: usize = 0x0143;
+const NEW_LICENSEE_ADDR: usize = 0x0144;
+const SGB_FLAG_ADDR: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDR: usize = 0x0147;
+const ROM_SIZE_ADDR: usize = 0x0148;
+const RAM_SIZE_ADDR: usize = 0x0149;
+const DESTINATION_ADDR: usize = 0x014a;
+const OLD_LICENSEE_ADDR: usize = 0x014b;
+const VERSION_ADDR: usize = 0x014c;
+const HEADER_CHECKSUM_ADDR: usize = 0x014d;
+const GLOBAL_CHECKSUM_ADDR: usize = 0x014e;
+
+/// The bitmap the boot ROM compares against before handing over to the cartridge.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0c, 0x00, 0x0d, 0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e,
+    0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99, 0xbb, 0xbb, 0x67, 0x63,
+    0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e
+];
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CgbFlag {
+    /// Monochrome only, the byte is part of the title.
+    None,
+    Supported,
+    Required
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    New(String)
+}
+
+/// The memory bank controller wired to the cartridge.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mapper {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1
+}
+
+/// Decoded form of the cartridge type byte at 0x0147.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+    pub sensor: bool
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Result<CartridgeType, CartridgeError> {
+        // mapper, ram, battery, timer, rumble, sensor
+        let (mapper, ram, battery, timer, rumble, sensor) = match code {
+            0x00 => (Mapper::RomOnly, false, false, false, false, false),
+            0x01 => (Mapper::Mbc1, false, false, false, false, false),
+            0x02 => (Mapper::Mbc1, true, false, false, false, false),
+            0x03 => (Mapper::Mbc1, true, true, false, false, false),
+            0x05 => (Mapper::Mbc2, false, false, false, false, false),
+            0x06 => (Mapper::Mbc2, false, true, false, false, false),
+            0x08 => (Mapper::RomOnly, true, false, false, false, false),
+            0x09 => (Mapper::RomOnly, true, true, false, false, false),
+            0x0b => (Mapper::Mmm01, false, false, false, false, false),
+            0x0c => (Mapper::Mmm01, true, false, false, false, false),
+            0x0d => (Mapper::Mmm01, true, true, false, false, false),
+            0x0f => (Mapper::Mbc3, false, true, true, false, false),
+            0x10 => (Mapper::Mbc3, true, true, true, false, false),
+            0x11 => (Mapper::Mbc3, false, false, false, false, false),
+            0x12 => (Mapper::Mbc3, true, false, false, false, false),
+            0x13 => (Mapper::Mbc3, true, true, false, false, false),
+            0x19 => (Mapper::Mbc5, false, false, false, false, false),
+            0x1a => (Mapper::Mbc5, true, false, false, false, false),
+            0x1b => (Mapper::Mbc5, true, true, false, false, false),
+            0x1c => (Mapper::Mbc5, false, false, false, true, false),
+            0x1d => (Mapper::Mbc5, true, false, false, true, false),
+            0x1e => (Mapper::Mbc5, true, true, false, true, false),
+            0x20 => (Mapper::Mbc6, true, true, false, false, false),
+            0x22 => (Mapper::Mbc7, true, true, false, true, true),
+            0xfc => (Mapper::PocketCamera, true, true, false, false, true),
+            0xfd => (Mapper::Tama5, true, true, true, false, false),
+            0xfe => (Mapper::HuC3, true, true, true, false, false),
+            0xff => (Mapper::HuC1, true, true, false, false, false),
+            _ => return Err(CartridgeError::UnknownCartridgeType(code))
+        };
+
+        Ok(CartridgeType { code, mapper, ram, battery, timer, rumble, sensor })
+    }
+}
+
+/// The cartridge header found at 0x0100-0x014F.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_flag: CgbFlag,
+    pub new_licensee_code: String,
+    pub sgb_flag: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination: Destination,
+    pub old_licensee_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub logo: [u8; 48]
+}
+
+impl Header {
+    /// Decodes the header fields without checking the logo or checksums,
+    /// see `Cartridge::validate` for that.
+    pub fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated { len: rom.len() });
+        }
+
+        let cgb_flag = match rom[CGB_FLAG_ADDR] {
+            0xc0 => CgbFlag::Required,
+            flag if flag & 0x80 != 0 => CgbFlag::Supported,
+            _ => CgbFlag::None
+        };
+
+        // newer CGB titles shorten the title to 11 bytes and put a four
+        // character manufacturer code after it
+        let manufacturer = &rom[MANUFACTURER_ADDR..CGB_FLAG_ADDR];
+        let has_manufacturer_code = cgb_flag != CgbFlag::None
+            && manufacturer.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+
+        let title_end = match (cgb_flag, has_manufacturer_code) {
+            (_, true) => MANUFACTURER_ADDR,
+            (CgbFlag::None, _) => NEW_LICENSEE_ADDR,
+            _ => CGB_FLAG_ADDR
+        };
+
+        let manufacturer_code = if has_manufacturer_code {
+            Some(ascii_string(manufacturer))
+        } else {
+            None
+        };
+
+        let rom_size_code = rom[ROM_SIZE_ADDR];
+        let ram_size_code = rom[RAM_SIZE_ADDR];
+
+        rom_banks_for_code(rom_size_code)?;
+        ram_size_for_code(ram_size_code)?;
+
+        let mut logo = [0; 48];
+        logo.copy_from_slice(&rom[LOGO_ADDR..TITLE_ADDR]);
+
+        Ok(Header {
+            title: ascii_string(&rom[TITLE_ADDR..title_end]),
+            manufacturer_code,
+            cgb_flag,
+            new_licensee_code: ascii_string(&rom[NEW_LICENSEE_ADDR..SGB_FLAG_ADDR]),
+            sgb_flag: rom[SGB_FLAG_ADDR] == 0x03,
+            cartridge_type: CartridgeType::from_code(rom[CARTRIDGE_TYPE_ADDR])?,
+            rom_size_code,
+            ram_size_code,
+            destination: match rom[DESTINATION_ADDR] {
+                0x00 => Destination::Japan,
+                _ => Destination::Overseas
+            },
+            old_licensee_code: rom[OLD_LICENSEE_ADDR],
+            version: rom[VERSION_ADDR],
+            header_checksum: rom[HEADER_CHECKSUM_ADDR],
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM_ADDR], rom[GLOBAL_CHECKSUM_ADDR + 1]]),
+            logo
+        })
+    }
+
+    /// 0x33 in the old licensee byte defers to the two character new code.
+    pub fn licensee(&self) -> Licensee {
+        match self.old_licensee_code {
+            0x33 => Licensee::New(self.new_licensee_code.clone()),
+            code => Licensee::Old(code)
+        }
+    }
+
+    pub fn rom_banks(&self) -> usize {
+        rom_banks_for_code(self.rom_size_code).unwrap()
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.rom_banks() * ROM_BANK_SIZE
+    }
+
+    pub fn ram_size(&self) -> usize {
+        ram_size_for_code(self.ram_size_code).unwrap()
+    }
+
+    pub fn logo_matches(&self) -> bool {
+        self.logo == NINTENDO_LOGO
+    }
+
+    /// The checksum over 0x0134-0x014C the boot ROM verifies.
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_ADDR..HEADER_CHECKSUM_ADDR].iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+    }
+
+    /// The sum of every byte in the image except the checksum itself. Real
+    /// hardware never checks this one.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(addr, _)| *addr != GLOBAL_CHECKSUM_ADDR && *addr != GLOBAL_CHECKSUM_ADDR + 1)
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+    }
+}
+
+fn rom_banks_for_code(code: u8) -> Result<usize, CartridgeError> {
+    match code {
+        0x00..=0x08 => Ok(2 << code),
+        // only seen in a handful of unofficial headers
+        0x52 => Ok(72),
+        0x53 => Ok(80),
+        0x54 => Ok(96),
+        _ => Err(CartridgeError::UnknownRomSize(code))
+    }
+}
+
+fn ram_size_for_code(code: u8) -> Result<usize, CartridgeError> {
+    match code {
+        0x00 => Ok(0),
+        0x01 => Ok(0x800),
+        0x02 => Ok(RAM_BANK_SIZE),
+        0x03 => Ok(RAM_BANK_SIZE * 4),
+        0x04 => Ok(RAM_BANK_SIZE * 16),
+        0x05 => Ok(RAM_BANK_SIZE * 8),
+        _ => Err(CartridgeError::UnknownRamSize(code))
+    }
+}
+
+fn ascii_string(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|c| **c != 0)
+        .map(|c| if c.is_ascii_graphic() || *c == b' ' { *c as char } else { '?' })
+        .collect()
+}
+
+/// An image of `2 << rom_size` empty banks with a valid logo and checksums
+/// around the given header bytes, for tests.
+#[cfg(test)]
+pub fn test_image(title: &[u8], cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; ROM_BANK_SIZE * (2 << rom_size)];
+
+    rom[LOGO_ADDR..TITLE_ADDR].copy_from_slice(&NINTENDO_LOGO);
+    rom[TITLE_ADDR..TITLE_ADDR + title.len()].copy_from_slice(title);
+    rom[CARTRIDGE_TYPE_ADDR] = cartridge_type;
+    rom[ROM_SIZE_ADDR] = rom_size;
+    rom[RAM_SIZE_ADDR] = ram_size;
+    rom[OLD_LICENSEE_ADDR] = 0x01;
+    rom[VERSION_ADDR] = 0x02;
+    fix_checksums(&mut rom);
+
+    rom
+}
+
+/// Recomputes both checksums after a test changed the image.
+#[cfg(test)]
+pub fn fix_checksums(rom: &mut [u8]) {
+    rom[HEADER_CHECKSUM_ADDR] = Header::compute_header_checksum(rom);
+
+    let global = Header::compute_global_checksum(rom);
+    rom[GLOBAL_CHECKSUM_ADDR..GLOBAL_CHECKSUM_ADDR + 2].copy_from_slice(&global.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    #[test]
+    fn parses_the_header_fields() {
+        let rom = test_image(b"TETRIS", 0x03, 0x01, 0x02);
+        let header = Header::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.manufacturer_code, None);
+        assert_eq!(header.cgb_flag, CgbFlag::None);
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc1);
+        assert!(header.cartridge_type.ram);
+        assert!(header.cartridge_type.battery);
+        assert_eq!(header.rom_banks(), 4);
+        assert_eq!(header.rom_size(), 0x10000);
+        assert_eq!(header.ram_size(), RAM_BANK_SIZE);
+        assert_eq!(header.licensee(), Licensee::Old(0x01));
+        assert_eq!(header.version, 0x02);
+        assert!(header.logo_matches());
+    }
+
+    #[test]
+    fn splits_off_the_cgb_manufacturer_code() {
+        let mut rom = test_image(b"POKEMON_SLVAAXE", 0x10, 0x00, 0x03);
+        rom[CGB_FLAG_ADDR] = 0x80;
+        rom[OLD_LICENSEE_ADDR] = 0x33;
+        rom[NEW_LICENSEE_ADDR..SGB_FLAG_ADDR].copy_from_slice(b"01");
+
+        let header = Header::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer_code.as_deref(), Some("AAXE"));
+        assert_eq!(header.cgb_flag, CgbFlag::Supported);
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc3);
+        assert!(header.cartridge_type.timer);
+        assert_eq!(header.licensee(), Licensee::New(String::from("01")));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let rom = test_image(b"SHORT", 0x00, 0x00, 0x00);
+
+        assert!(matches!(Header::parse(&rom[..HEADER_END - 1]), Err(CartridgeError::Truncated { len }) if len == HEADER_END - 1));
+        assert!(matches!(Cartridge::from_bytes(Vec::new()), Err(CartridgeError::Truncated { len: 0 })));
+        assert!(Header::parse(&rom[..HEADER_END]).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_codes() {
+        let mut rom = test_image(b"CODES", 0x00, 0x00, 0x00);
+
+        rom[CARTRIDGE_TYPE_ADDR] = 0x04;
+        assert!(matches!(Header::parse(&rom), Err(CartridgeError::UnknownCartridgeType(0x04))));
+
+        rom[CARTRIDGE_TYPE_ADDR] = 0x00;
+        rom[ROM_SIZE_ADDR] = 0x09;
+        assert!(matches!(Header::parse(&rom), Err(CartridgeError::UnknownRomSize(0x09))));
+
+        rom[ROM_SIZE_ADDR] = 0x00;
+        rom[RAM_SIZE_ADDR] = 0x06;
+        assert!(matches!(Header::parse(&rom), Err(CartridgeError::UnknownRamSize(0x06))));
+    }
+
+    #[test]
+    fn validates_the_checksums() {
+        let rom = test_image(b"CHECKSUMS", 0x00, 0x00, 0x00);
+        let cartridge = Cartridge::from_bytes(rom.clone()).unwrap();
+
+        assert!(cartridge.validate().is_ok());
+        assert!(cartridge.verify_global_checksum().is_ok());
+
+        let mut bad_header = rom.clone();
+        bad_header[HEADER_CHECKSUM_ADDR] ^= 0xff;
+        let expected = bad_header[HEADER_CHECKSUM_ADDR];
+        let actual = rom[HEADER_CHECKSUM_ADDR];
+
+        let cartridge = Cartridge::from_bytes(bad_header).unwrap();
+        assert!(matches!(cartridge.validate(),
+            Err(CartridgeError::HeaderChecksum { expected: e, actual: a }) if e == expected && a == actual));
+
+        let mut bad_global = rom.clone();
+        bad_global[0x0200] = 0x42;
+
+        let cartridge = Cartridge::from_bytes(bad_global).unwrap();
+        assert!(cartridge.validate().is_ok());
+        assert!(matches!(cartridge.verify_global_checksum(),
+            Err(CartridgeError::GlobalChecksum { actual, .. }) if actual == Header::compute_global_checksum(&rom).wrapping_add(0x42)));
+    }
+
+    #[test]
+    fn validates_the_logo_and_size() {
+        let mut rom = test_image(b"LOGO", 0x00, 0x00, 0x00);
+        rom[LOGO_ADDR] = 0x00;
+
+        assert!(matches!(Cartridge::from_bytes(rom).unwrap().validate(), Err(CartridgeError::BadLogo)));
+
+        let mut rom = test_image(b"SIZE", 0x00, 0x01, 0x00);
+        rom.truncate(0x8000);
+
+        assert!(matches!(Cartridge::from_bytes(rom).unwrap().validate(),
+            Err(CartridgeError::RomSizeMismatch { declared: 0x10000, actual: 0x8000 })));
+    }
+}
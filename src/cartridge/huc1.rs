@@ -0,0 +1,117 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte, ram_index, store_ram };
+
+/// HuC1, Hudson's mapper with an infrared LED and receiver.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | 0x0E maps the IR port at 0xA000, else RAM       |
+/// | 2000-3FFF   | six bit ROM bank                                |
+/// | 4000-5FFF   | RAM bank 0-3                                    |
+///
+/// There is no RAM enable, RAM is always accessible in RAM mode. Nothing is
+/// ever on the other end of the IR port, so the receiver never sees light.
+pub struct HuC1 {
+    ir_mode: bool,
+    ir_led: bool,
+    rom_bank: u8,
+    ram_bank: u8
+}
+
+impl HuC1 {
+    pub fn new() -> HuC1 {
+        HuC1 {
+            ir_mode: false,
+            ir_led: false,
+            rom_bank: 1,
+            ram_bank: 0
+        }
+    }
+
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+}
+
+impl Default for HuC1 {
+    fn default() -> Self {
+        HuC1::new()
+    }
+}
+
+impl Mbc for HuC1 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ir_mode = value & 0x0f == 0x0e,
+            0x2000..=0x3fff => self.rom_bank = value & 0x3f,
+            0x4000..=0x5fff => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if self.ir_mode {
+            // bit 0 clear means no light is being received
+            return 0xc0;
+        }
+
+        ram_index(ram, self.ram_bank as usize, addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if self.ir_mode {
+            self.ir_led = value & 0x01 != 0;
+            return false;
+        }
+
+        store_ram(ram, self.ram_bank as usize, addr, value)
+    }
+
+    fn reset(&mut self) {
+        *self = HuC1::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    #[test]
+    fn switches_banks() {
+        let rom = numbered_rom(64);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = HuC1::new();
+
+        mbc.write_rom(0x2000, 0x3f);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x3f);
+
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xa001, 0x42);
+        assert_eq!(ram[3 * 0x2000 + 1], 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xa001), 0x42);
+    }
+
+    #[test]
+    fn ir_mode_replaces_ram() {
+        let mut ram = vec![0; 0x2000];
+        let mut mbc = HuC1::new();
+
+        mbc.write_rom(0x0000, 0x0e);
+        mbc.write_ram(&mut ram, 0xa000, 0x01);
+        assert!(mbc.ir_led());
+        assert_eq!(ram[0], 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0xc0);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0x00);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::cartridge::mbc::{ Mbc, CLOCK_SPEED, rom_byte, ram_index, store_ram, unix_time };
+
+/// Size of the clock state saved after the RAM: a little endian u64 unix
+/// timestamp followed by little endian u16 minutes, days, alarm minutes and
+/// alarm days, and an alarm enable byte.
+pub const HUC3_TRAILER_SIZE: usize = 17;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// HuC3, Hudson's mapper with a clock, a speaker and an infrared port.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | what 0xA000-0xBFFF maps, see below              |
+/// | 2000-3FFF   | seven bit ROM bank                              |
+/// | 4000-5FFF   | RAM bank 0-3                                    |
+///
+/// The modes selected through 0x0000-0x1FFF are 0x0A for RAM, 0x0B to send
+/// a command to the clock, 0x0C to read its response, 0x0D for the
+/// ready flag and 0x0E for the IR port. Anything else leaves RAM read only.
+///
+/// The clock is a small microcontroller with 256 nibbles of memory. Commands
+/// are a nibble opcode and a nibble argument:
+///
+/// | opcode | effect                                                     |
+/// |--------|------------------------------------------------------------|
+/// | 0x1    | read the nibble at the address pointer, then increment it  |
+/// | 0x3    | write the argument at the address pointer, then increment  |
+/// | 0x4    | set the low nibble of the address pointer                  |
+/// | 0x5    | set the high nibble of the address pointer                 |
+/// | 0x6    | 0x0 copies the time to 0x00-0x05, 0x1 sets it from there   |
+pub struct HuC3 {
+    mode: u8,
+    rom_bank: u8,
+    ram_bank: u8,
+    memory: [u8; 256],
+    address: u8,
+    command: u8,
+    response: u8,
+    minutes: u16,
+    days: u16,
+    alarm_minutes: u16,
+    alarm_days: u16,
+    alarm_enabled: bool,
+    cycles: u64
+}
+
+impl HuC3 {
+    pub fn new() -> HuC3 {
+        HuC3 {
+            mode: 0,
+            rom_bank: 1,
+            ram_bank: 0,
+            memory: [0; 256],
+            address: 0,
+            command: 0,
+            response: 0,
+            minutes: 0,
+            days: 0,
+            alarm_minutes: 0,
+            alarm_days: 0,
+            alarm_enabled: false,
+            cycles: 0
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.minutes as u64 + minutes;
+
+        self.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+        self.days = ((self.days as u64 + total / MINUTES_PER_DAY as u64) & 0x0fff) as u16;
+    }
+
+    /// Returns whether the clock was set.
+    fn run_command(&mut self, value: u8) -> bool {
+        self.command = value >> 4;
+        let argument = value & 0x0f;
+
+        match self.command {
+            0x1 => {
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            },
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            },
+            0x4 => self.address = (self.address & 0xf0) | argument,
+            0x5 => self.address = (self.address & 0x0f) | (argument << 4),
+            0x6 => match argument {
+                0x0 => {
+                    let time = self.minutes as u32 | ((self.days as u32) << 12);
+                    for nibble in 0..6 {
+                        self.memory[nibble] = ((time >> (nibble * 4)) & 0x0f) as u8;
+                    }
+                },
+                0x1 => {
+                    let time = (0..6).fold(0u32, |time, nibble| {
+                        time | ((self.memory[nibble] as u32 & 0x0f) << (nibble * 4))
+                    });
+                    self.minutes = ((time & 0x0fff) as u16).min(MINUTES_PER_DAY - 1);
+                    self.days = ((time >> 12) & 0x0fff) as u16;
+                    self.cycles = 0;
+                    return true;
+                },
+                // status query, always ready
+                0x2 => self.response = 0x1,
+                _ => {}
+            },
+            _ => {}
+        }
+
+        false
+    }
+}
+
+impl Default for HuC3 {
+    fn default() -> Self {
+        HuC3::new()
+    }
+}
+
+impl Mbc for HuC3 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.mode = value & 0x0f,
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            0x4000..=0x5fff => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.mode {
+            0x0c => 0x80 | (self.command << 4) | self.response,
+            0x0d => 0x01,
+            // no light on the IR receiver
+            0x0e => 0xc0,
+            _ => ram_index(ram, self.ram_bank as usize, addr).map_or(0xff, |index| ram[index])
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self.mode {
+            0x0a => store_ram(ram, self.ram_bank as usize, addr, value),
+            0x0b => self.run_command(value),
+            _ => false
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = HuC3 {
+            minutes: self.minutes,
+            days: self.days,
+            alarm_minutes: self.alarm_minutes,
+            alarm_days: self.alarm_days,
+            alarm_enabled: self.alarm_enabled,
+            cycles: self.cycles,
+            ..HuC3::new()
+        };
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        const CYCLES_PER_MINUTE: u64 = CLOCK_SPEED as u64 * 60;
+
+        self.cycles += cycles as u64;
+
+        if self.cycles >= CYCLES_PER_MINUTE {
+            self.cycles -= CYCLES_PER_MINUTE;
+            self.advance_minutes(1);
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HUC3_TRAILER_SIZE);
+
+        data.extend_from_slice(&unix_time().to_le_bytes());
+        data.extend_from_slice(&self.minutes.to_le_bytes());
+        data.extend_from_slice(&self.days.to_le_bytes());
+        data.extend_from_slice(&self.alarm_minutes.to_le_bytes());
+        data.extend_from_slice(&self.alarm_days.to_le_bytes());
+        data.push(self.alarm_enabled as u8);
+
+        data
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if data.len() < HUC3_TRAILER_SIZE {
+            return;
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&data[0..8]);
+
+        self.minutes = word(8).min(MINUTES_PER_DAY - 1);
+        self.days = word(10) & 0x0fff;
+        self.alarm_minutes = word(12);
+        self.alarm_days = word(14);
+        self.alarm_enabled = data[16] != 0;
+        self.cycles = 0;
+
+        let elapsed = unix_time().saturating_sub(u64::from_le_bytes(timestamp));
+        self.advance_minutes(elapsed / 60);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    fn send(mbc: &mut HuC3, command: u8) {
+        mbc.write_rom(0x0000, 0x0b);
+        mbc.write_ram(&mut [], 0xa000, command);
+    }
+
+    fn response(mbc: &mut HuC3) -> u8 {
+        mbc.write_rom(0x0000, 0x0c);
+        mbc.read_ram(&[], 0xa000) & 0x0f
+    }
+
+    fn set_address(mbc: &mut HuC3, address: u8) {
+        send(mbc, 0x40 | (address & 0x0f));
+        send(mbc, 0x50 | (address >> 4));
+    }
+
+    #[test]
+    fn ram_is_only_writable_in_ram_mode() {
+        let rom = numbered_rom(128);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = HuC3::new();
+
+        mbc.write_rom(0x2000, 0x7f);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x7f);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[2 * 0x2000], 0x00);
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[2 * 0x2000], 0x42);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0x42);
+    }
+
+    #[test]
+    fn sets_and_reads_the_clock() {
+        let mut mbc = HuC3::new();
+
+        // 0x123 minutes on day 0x045, written a nibble at a time
+        set_address(&mut mbc, 0x00);
+        for nibble in [0x3, 0x2, 0x1, 0x5, 0x4, 0x0] {
+            send(&mut mbc, 0x30 | nibble);
+        }
+        send(&mut mbc, 0x61);
+
+        mbc.tick(CLOCK_SPEED * 60);
+        send(&mut mbc, 0x60);
+
+        set_address(&mut mbc, 0x00);
+        let nibbles: Vec<u8> = (0..6).map(|_| {
+            send(&mut mbc, 0x10);
+            response(&mut mbc)
+        }).collect();
+
+        assert_eq!(nibbles, [0x4, 0x2, 0x1, 0x5, 0x4, 0x0]);
+
+        send(&mut mbc, 0x62);
+        assert_eq!(response(&mut mbc), 0x1);
+    }
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut mbc = HuC3::new();
+        mbc.advance_minutes(3 * MINUTES_PER_DAY as u64 + 17);
+
+        let data = mbc.save_state();
+        assert_eq!(data.len(), HUC3_TRAILER_SIZE);
+
+        let mut loaded = HuC3::new();
+        loaded.load_state(&data);
+        assert_eq!((loaded.minutes, loaded.days), (17, 3));
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use crate::cartridge::camera::ImageSource;
+use crate::cartridge::header::{ ROM_BANK_SIZE, RAM_BANK_SIZE };
+use crate::cartridge::mbc7::TiltSource;
+
+/// Cycles per second, for mappers that keep real time.
+pub const CLOCK_SPEED: u32 = 4_194_304;
+
+/// A memory bank controller. The cartridge owns the ROM and RAM, the mapper
+/// only decides which bytes of them the CPU sees and reacts to register writes.
+pub trait Mbc {
+    /// Reads from 0x0000-0x7FFF.
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8;
+
+    /// Writes to 0x0000-0x7FFF, which land in the mapper's registers.
+    /// Returns whether anything that goes into the save data changed, as
+    /// flash programmed through this range does.
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool;
+
+    /// Reads from 0xA000-0xBFFF.
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8;
+
+    /// Writes to 0xA000-0xBFFF. Returns whether anything that goes into
+    /// the save data was stored, which a disabled or missing RAM doesn't.
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool;
+
+    /// Puts the registers back to their power on state for a reset. What
+    /// goes into the save data, such as a clock or flash, is kept.
+    fn reset(&mut self);
+
+    /// Advances anything on the cartridge that keeps time, in CPU cycles.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// Mapper state that is saved after the RAM, such as a clock.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, _data: &[u8]) {}
+
+    /// Whether the rumble motor is currently driven.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Only used by mappers with an accelerometer.
+    fn set_tilt_source(&mut self, _source: TiltSource) {}
+
+    /// Only used by mappers with an image sensor.
+    fn set_image_source(&mut self, _source: ImageSource) {}
+}
+
+/// Reads `offset` within a 16 KiB ROM bank. Bank numbers past the end of the
+/// image wrap, as the unused upper bank lines are not connected.
+pub fn rom_byte(rom: &[u8], bank: usize, offset: u16) -> u8 {
+    let banks = (rom.len() / ROM_BANK_SIZE).max(1);
+    let addr = (bank % banks) * ROM_BANK_SIZE + (offset as usize & (ROM_BANK_SIZE - 1));
+
+    rom.get(addr).copied().unwrap_or(0xff)
+}
+
+/// Maps `offset` within an 8 KiB RAM bank to an index into the RAM,
+/// wrapping like `rom_byte`. Returns None when there is no RAM at all.
+pub fn ram_index(ram: &[u8], bank: usize, offset: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+
+    Some((bank * RAM_BANK_SIZE + (offset as usize & (RAM_BANK_SIZE - 1))) % ram.len())
+}
+
+/// A ROM of `banks` 16 KiB banks, each filled with its own number as
+/// little endian u16s so tests can tell which one is mapped.
+#[cfg(test)]
+pub fn numbered_rom(banks: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| (bank as u16).to_le_bytes().repeat(ROM_BANK_SIZE / 2)).collect()
+}
+
+/// The number of the `numbered_rom` bank `mbc` maps at `addr`.
+#[cfg(test)]
+pub fn mapped_bank<M: Mbc>(mbc: &M, rom: &[u8], addr: u16) -> usize {
+    u16::from_le_bytes([mbc.read_rom(rom, addr & !1), mbc.read_rom(rom, addr | 1)]) as usize
+}
+
+/// Stores `value` at `offset` within an 8 KiB RAM bank, see `ram_index`.
+/// Returns false when there is no RAM to store it in.
+pub fn store_ram(ram: &mut [u8], bank: usize, offset: u16, value: u8) -> bool {
+    match ram_index(ram, bank, offset) {
+        Some(index) => {
+            ram[index] = value;
+            true
+        },
+        None => false
+    }
+}
+
+/// Seconds since the unix epoch, used to catch clocks up on time spent
+/// with the emulator closed.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Cartridges with at most 32 KiB of ROM and an optional 8 KiB of RAM
+/// that need no banking at all.
+pub struct RomOnly;
+
+impl Mbc for RomOnly {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom.get(addr as usize).copied().unwrap_or(0xff)
+    }
+
+    fn write_rom(&mut self, _addr: u16, _value: u8) -> bool {
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        ram_index(ram, 0, addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        store_ram(ram, 0, addr, value)
+    }
+
+    fn reset(&mut self) {}
+}
@@ -0,0 +1,213 @@
+use crate::cartridge::header::{ NINTENDO_LOGO, ROM_BANK_SIZE };
+use crate::cartridge::mbc::{ Mbc, rom_byte, ram_index, store_ram };
+
+/// MBC1, with up to 2 MiB of ROM and 32 KiB of RAM.
+///
+/// | range       | register                                       |
+/// |-------------|------------------------------------------------|
+/// | 0000-1FFF   | RAM enable, 0x0A in the low nibble enables     |
+/// | 2000-3FFF   | low five bits of the ROM bank                  |
+/// | 4000-5FFF   | RAM bank, or bits 5-6 of the ROM bank          |
+/// | 6000-7FFF   | banking mode select                            |
+///
+/// The multicart ("MBC1M") variant wires only four bits of the low bank
+/// register so the upper register selects one of four 256 KiB games.
+pub struct Mbc1 {
+    ram_enabled: bool,
+    bank_low: u8,
+    bank_high: u8,
+    advanced_mode: bool,
+    multicart: bool
+}
+
+impl Mbc1 {
+    pub fn new(rom: &[u8]) -> Mbc1 {
+        Mbc1 {
+            ram_enabled: false,
+            bank_low: 1,
+            bank_high: 0,
+            advanced_mode: false,
+            multicart: Mbc1::is_multicart(rom)
+        }
+    }
+
+    /// Multicarts are 1 MiB images where the games after the menu carry
+    /// their own header, so the logo shows up again at the start of one of
+    /// the 256 KiB blocks.
+    pub fn is_multicart(rom: &[u8]) -> bool {
+        if rom.len() != 64 * ROM_BANK_SIZE {
+            return false;
+        }
+
+        [0x10, 0x20, 0x30].iter().any(|bank| {
+            let logo = bank * ROM_BANK_SIZE + 0x0104;
+            rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+    }
+
+    fn high_shift(&self) -> u8 {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    /// Bank mapped into 0x0000-0x3FFF, only affected by the upper register
+    /// in the advanced banking mode.
+    fn zero_bank(&self) -> usize {
+        if self.advanced_mode {
+            (self.bank_high << self.high_shift()) as usize
+        } else {
+            0
+        }
+    }
+
+    fn high_bank(&self) -> usize {
+        let low = if self.multicart { self.bank_low & 0x0f } else { self.bank_low };
+
+        ((self.bank_high << self.high_shift()) | low) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_mode {
+            self.bank_high as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, self.zero_bank(), addr),
+            _ => rom_byte(rom, self.high_bank(), addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ram_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => {
+                // the zero check only looks at the five register bits, so
+                // banks 0x20, 0x40 and 0x60 end up as 0x21, 0x41 and 0x61
+                self.bank_low = value & 0x1f;
+                if self.bank_low == 0 {
+                    self.bank_low = 1;
+                }
+            },
+            0x4000..=0x5fff => self.bank_high = value & 0x03,
+            _ => self.advanced_mode = value & 0x01 != 0
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+
+        ram_index(ram, self.ram_bank(), addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        store_ram(ram, self.ram_bank(), addr, value)
+    }
+
+    fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.bank_low = 1;
+        self.bank_high = 0;
+        self.advanced_mode = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    #[test]
+    fn switches_rom_banks() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mbc1::new(&rom);
+
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 5);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0);
+
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x7ffe), 0x25);
+
+        // only the low five bits are written
+        mbc.write_rom(0x2000, 0xe3);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x23);
+    }
+
+    #[test]
+    fn bank_zero_quirk() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 1);
+
+        // the zero check misses the upper bits, so 0x20, 0x40 and 0x60 can't be reached
+        for high in 1..4 {
+            mbc.write_rom(0x4000, high);
+            mbc.write_rom(0x2000, 0x20);
+            assert_eq!(mapped_bank(&mbc, &rom, 0x4000), ((high as usize) << 5) | 1);
+        }
+    }
+
+    #[test]
+    fn advanced_mode_banks_the_low_area_and_ram() {
+        let rom = numbered_rom(128);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xa000, 0x11);
+        assert_eq!(ram[0], 0x11);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0x40);
+        mbc.write_ram(&mut ram, 0xa000, 0x22);
+        assert_eq!(ram[2 * 0x2000], 0x22);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0x22);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0xff);
+        mbc.write_ram(&mut ram, 0xa000, 0x33);
+        assert_eq!(ram[2 * 0x2000], 0x22);
+    }
+
+    #[test]
+    fn multicart_wiring() {
+        let mut rom = numbered_rom(64);
+        assert!(!Mbc1::is_multicart(&rom));
+
+        let logo = 0x10 * ROM_BANK_SIZE + 0x0104;
+        rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        assert!(Mbc1::is_multicart(&rom));
+
+        let mut mbc = Mbc1::new(&rom);
+
+        // bit 4 of the low register is not connected
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x12);
+
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x32);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0x30);
+    }
+}
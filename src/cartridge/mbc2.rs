@@ -0,0 +1,123 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte };
+
+/// MBC2 has 512 half-bytes of RAM built into the mapper itself.
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+/// MBC2, with up to 256 KiB of ROM.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-3FFF   | address bit 8 clear: RAM enable, 0x0A enables   |
+/// |             | address bit 8 set: four bit ROM bank, 0 is 1    |
+///
+/// The built-in RAM only stores the low nibble of each byte and is echoed
+/// through the whole of 0xA000-0xBFFF.
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8
+}
+
+impl Mbc2 {
+    pub fn new() -> Mbc2 {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 1
+        }
+    }
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Mbc2::new()
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x3fff if addr & 0x0100 == 0 => self.ram_enabled = value & 0x0f == 0x0a,
+            0x0000..=0x3fff => {
+                self.rom_bank = value & 0x0f;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled || ram.is_empty() {
+            return 0xff;
+        }
+
+        // the upper nibble is not connected and floats high
+        0xf0 | ram[addr as usize % MBC2_RAM_SIZE]
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled || ram.is_empty() {
+            return false;
+        }
+
+        ram[addr as usize % MBC2_RAM_SIZE] = value & 0x0f;
+        true
+    }
+
+    fn reset(&mut self) {
+        *self = Mbc2::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    #[test]
+    fn address_bit_8_selects_the_register() {
+        let rom = numbered_rom(16);
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        let mut mbc = Mbc2::new();
+
+        // bit 8 clear: RAM enable
+        mbc.write_rom(0x2000, 0x0a);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 1);
+        mbc.write_ram(&mut ram, 0xa000, 0x05);
+        assert_eq!(ram[0], 0x05);
+
+        // bit 8 set: ROM bank
+        mbc.write_rom(0x2100, 0x0a);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x0a);
+        mbc.write_rom(0x0100, 0x10);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 1);
+        mbc.write_rom(0x3fff, 0x0f);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x0f);
+    }
+
+    #[test]
+    fn ram_holds_nibbles_and_echoes() {
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        let mut mbc = Mbc2::new();
+
+        mbc.write_ram(&mut ram, 0xa000, 0x0c);
+        assert_eq!(ram[0], 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0xff);
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_ram(&mut ram, 0xa001, 0xab);
+        assert_eq!(ram[1], 0x0b);
+        assert_eq!(mbc.read_ram(&ram, 0xa001), 0xfb);
+        assert_eq!(mbc.read_ram(&ram, 0xa201), 0xfb);
+        assert_eq!(mbc.read_ram(&ram, 0xbe01), 0xfb);
+    }
+}
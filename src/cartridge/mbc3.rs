@@ -0,0 +1,428 @@
+use crate::cartridge::mbc::{ Mbc, CLOCK_SPEED, rom_byte, ram_index, store_ram, unix_time };
+
+/// Size of the clock trailer appended to the RAM in save files, the layout
+/// BGB and VBA-M use: five live registers, five latched registers (each as a
+/// little endian u32) and a little endian u64 unix timestamp.
+pub const RTC_TRAILER_SIZE: usize = 48;
+
+const DH_DAY_HIGH: u8 = 0x01;
+const DH_HALT: u8 = 0x40;
+const DH_CARRY: u8 = 0x80;
+
+/// The MBC3 real time clock. Registers are kept in the order they are
+/// selected through 0x4000-0x5FFF, 0x08 being seconds.
+#[derive(Debug, Clone, Default)]
+pub struct Rtc {
+    live: [u8; 5],
+    latched: [u8; 5],
+    cycles: u32
+}
+
+impl Rtc {
+    const SECONDS: usize = 0;
+    const MINUTES: usize = 1;
+    const HOURS: usize = 2;
+    const DAY_LOW: usize = 3;
+    const DAY_HIGH: usize = 4;
+
+    /// Only the bits that exist in hardware are kept.
+    const MASKS: [u8; 5] = [0x3f, 0x3f, 0x1f, 0xff, DH_DAY_HIGH | DH_HALT | DH_CARRY];
+
+    fn halted(&self) -> bool {
+        self.live[Rtc::DAY_HIGH] & DH_HALT != 0
+    }
+
+    fn days(&self) -> u16 {
+        self.live[Rtc::DAY_LOW] as u16 | (((self.live[Rtc::DAY_HIGH] & DH_DAY_HIGH) as u16) << 8)
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.live[Rtc::DAY_LOW] = (days & 0xff) as u8;
+        self.live[Rtc::DAY_HIGH] = (self.live[Rtc::DAY_HIGH] & !DH_DAY_HIGH) | ((days >> 8) as u8 & DH_DAY_HIGH);
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    /// Sets a register. The write shows up in the latched copy too, so it
+    /// reads back straight away without relatching.
+    pub fn write(&mut self, register: u8, value: u8) {
+        let index = (register - 0x08) as usize;
+        self.live[index] = value & Rtc::MASKS[index];
+        self.latched[index] = self.live[index];
+
+        // writing the seconds restarts the current second
+        if index == Rtc::SECONDS {
+            self.cycles = 0;
+        }
+    }
+
+    pub fn latch(&mut self) {
+        self.latched = self.live;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halted() {
+            return;
+        }
+
+        self.cycles += cycles;
+
+        while self.cycles >= CLOCK_SPEED {
+            self.cycles -= CLOCK_SPEED;
+            self.tick_second();
+        }
+    }
+
+    /// Counters only carry when they hit their real limit, a register
+    /// written out of range counts up to its bit width and wraps silently.
+    fn tick_second(&mut self) {
+        self.live[Rtc::SECONDS] = (self.live[Rtc::SECONDS] + 1) & Rtc::MASKS[Rtc::SECONDS];
+        if self.live[Rtc::SECONDS] != 60 {
+            return;
+        }
+        self.live[Rtc::SECONDS] = 0;
+
+        self.live[Rtc::MINUTES] = (self.live[Rtc::MINUTES] + 1) & Rtc::MASKS[Rtc::MINUTES];
+        if self.live[Rtc::MINUTES] != 60 {
+            return;
+        }
+        self.live[Rtc::MINUTES] = 0;
+
+        self.live[Rtc::HOURS] = (self.live[Rtc::HOURS] + 1) & Rtc::MASKS[Rtc::HOURS];
+        if self.live[Rtc::HOURS] != 24 {
+            return;
+        }
+        self.live[Rtc::HOURS] = 0;
+
+        let days = self.days() + 1;
+        if days == 512 {
+            self.live[Rtc::DAY_HIGH] |= DH_CARRY;
+        }
+        self.set_days(days & 0x1ff);
+    }
+
+    /// Moves the clock forward by the time that passed while the emulator
+    /// was not running.
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halted() {
+            return;
+        }
+
+        let in_range = |live: &[u8; 5]| {
+            live[Rtc::SECONDS] < 60 && live[Rtc::MINUTES] < 60 && live[Rtc::HOURS] < 24
+        };
+
+        while seconds > 0 && !in_range(&self.live) {
+            self.tick_second();
+            seconds -= 1;
+        }
+
+        if seconds == 0 {
+            return;
+        }
+
+        let total = ((self.days() as u64 * 24 + self.live[Rtc::HOURS] as u64) * 60
+            + self.live[Rtc::MINUTES] as u64) * 60
+            + self.live[Rtc::SECONDS] as u64
+            + seconds;
+
+        let days = total / 86400;
+        if days >= 512 {
+            self.live[Rtc::DAY_HIGH] |= DH_CARRY;
+        }
+
+        self.live[Rtc::SECONDS] = (total % 60) as u8;
+        self.live[Rtc::MINUTES] = (total / 60 % 60) as u8;
+        self.live[Rtc::HOURS] = (total / 3600 % 24) as u8;
+        self.set_days((days % 512) as u16);
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_TRAILER_SIZE);
+
+        for register in self.live.iter().chain(self.latched.iter()) {
+            data.extend_from_slice(&(*register as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&unix_time().to_le_bytes());
+
+        data
+    }
+
+    /// Restores the registers from a save trailer and catches up on the time
+    /// since it was written. The 44 byte variant with a 32 bit timestamp
+    /// some tools write is accepted too.
+    pub fn load(&mut self, data: &[u8]) {
+        if data.len() < 44 {
+            return;
+        }
+
+        for (index, chunk) in data[..40].chunks_exact(4).enumerate() {
+            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as u8;
+
+            if index < 5 {
+                self.live[index] = value & Rtc::MASKS[index];
+            } else {
+                self.latched[index - 5] = value & Rtc::MASKS[index - 5];
+            }
+        }
+
+        let timestamp = if data.len() >= RTC_TRAILER_SIZE {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&data[40..48]);
+            u64::from_le_bytes(bytes)
+        } else {
+            u32::from_le_bytes([data[40], data[41], data[42], data[43]]) as u64
+        };
+
+        self.cycles = 0;
+        self.advance(unix_time().saturating_sub(timestamp));
+    }
+}
+
+/// MBC3, with up to 2 MiB of ROM, 32 KiB of RAM and an optional real time
+/// clock.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | RAM and clock enable, 0x0A enables              |
+/// | 2000-3FFF   | seven bit ROM bank, 0 selects 1                 |
+/// | 4000-5FFF   | RAM bank 0-3, or clock register 0x08-0x0C       |
+/// | 6000-7FFF   | writing 0 then 1 latches the clock              |
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_select: u8,
+    latch_armed: bool,
+    rtc: Option<Rtc>
+}
+
+impl Mbc3 {
+    pub fn new(has_timer: bool) -> Mbc3 {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch_armed: false,
+            rtc: if has_timer { Some(Rtc::default()) } else { None }
+        }
+    }
+
+    /// The clock register currently mapped at 0xA000-0xBFFF, if any.
+    fn rtc_register(&self) -> Option<u8> {
+        match self.ram_select {
+            0x08..=0x0c if self.rtc.is_some() => Some(self.ram_select),
+            _ => None
+        }
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ram_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => {
+                self.rom_bank = value & 0x7f;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            },
+            0x4000..=0x5fff => self.ram_select = value & 0x0f,
+            _ => {
+                if value == 0x01 && self.latch_armed {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.latch();
+                    }
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+
+        match (self.rtc_register(), &self.rtc) {
+            (Some(register), Some(rtc)) => rtc.read(register),
+            _ if self.ram_select < 0x04 => {
+                ram_index(ram, self.ram_select as usize, addr).map_or(0xff, |index| ram[index])
+            },
+            _ => 0xff
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        match (self.rtc_register(), self.rtc.as_mut()) {
+            (Some(register), Some(rtc)) => {
+                rtc.write(register, value);
+                true
+            },
+            _ if self.ram_select < 0x04 => store_ram(ram, self.ram_select as usize, addr, value),
+            _ => false
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Mbc3 { rtc: self.rtc.take(), ..Mbc3::new(false) };
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.rtc.as_ref().map_or_else(Vec::new, Rtc::save)
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.load(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_mbc3() -> Mbc3 {
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(0x0000, 0x0a);
+        mbc
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+    }
+
+    #[test]
+    fn switches_rom_and_ram_banks() {
+        let rom: Vec<u8> = (0..0x80).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = enabled_mbc3();
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2000, 0x7f);
+        assert_eq!(mbc.read_rom(&rom, 0x7fff), 0x7f);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xa010, 0x42);
+        assert_eq!(ram[2 * 0x2000 + 0x10], 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xa010), 0x42);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa010), 0xff);
+    }
+
+    #[test]
+    fn written_registers_read_back_before_latching() {
+        let mut ram = Vec::new();
+        let mut mbc = enabled_mbc3();
+
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xa000, 30);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 30);
+
+        mbc.write_rom(0x4000, 0x0a);
+        mbc.write_ram(&mut ram, 0xa000, 0xff);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0x1f);
+    }
+
+    #[test]
+    fn reads_hold_the_latched_time() {
+        let mut ram = Vec::new();
+        let mut mbc = enabled_mbc3();
+
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xa000, 59);
+        mbc.tick(CLOCK_SPEED);
+
+        // still the time of the write until the clock is latched
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 59);
+
+        latch(&mut mbc);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 0);
+        mbc.write_rom(0x4000, 0x09);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 1);
+
+        // only a 0 then 1 sequence latches
+        mbc.tick(CLOCK_SPEED * 60);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 1);
+
+        latch(&mut mbc);
+        assert_eq!(mbc.read_ram(&ram, 0xa000), 2);
+    }
+
+    #[test]
+    fn halted_clock_stands_still() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x0c, DH_HALT);
+        rtc.tick(CLOCK_SPEED * 10);
+        rtc.latch();
+
+        assert_eq!(rtc.read(0x08), 0);
+    }
+
+    #[test]
+    fn save_trailer_round_trips() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x08, 12);
+        rtc.write(0x09, 34);
+        rtc.write(0x0a, 5);
+        rtc.write(0x0b, 0x2c);
+        // halted so no time passes between saving and loading
+        rtc.write(0x0c, DH_HALT | DH_DAY_HIGH);
+        rtc.latch();
+        rtc.write(0x08, 13);
+
+        let data = rtc.save();
+        assert_eq!(data.len(), RTC_TRAILER_SIZE);
+        assert_eq!(&data[0..4], &13u32.to_le_bytes());
+        assert_eq!(&data[20..24], &13u32.to_le_bytes());
+
+        let mut loaded = Rtc::default();
+        loaded.load(&data);
+        assert_eq!(loaded.live, rtc.live);
+        assert_eq!(loaded.latched, rtc.latched);
+
+        let mut mbc = Mbc3::new(true);
+        mbc.load_state(&data);
+        assert_eq!(mbc.save_state()[..40], data[..40]);
+    }
+
+    #[test]
+    fn load_catches_up_on_elapsed_time() {
+        let mut data = Rtc::default().save();
+        let an_hour_ago = unix_time() - 3600;
+        data[40..48].copy_from_slice(&an_hour_ago.to_le_bytes());
+
+        let mut rtc = Rtc::default();
+        rtc.load(&data);
+
+        assert_eq!(rtc.live[Rtc::HOURS], 1);
+        assert_eq!(rtc.live[Rtc::MINUTES], 0);
+    }
+}
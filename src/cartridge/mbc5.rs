@@ -0,0 +1,144 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte, ram_index, store_ram };
+
+/// MBC5, with up to 8 MiB of ROM and 128 KiB of RAM.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | RAM enable, exactly 0x0A enables                |
+/// | 2000-2FFF   | low eight bits of the ROM bank                  |
+/// | 3000-3FFF   | ninth bit of the ROM bank                       |
+/// | 4000-5FFF   | RAM bank 0-F                                    |
+///
+/// Unlike the older mappers bank 0 can be mapped at 0x4000-0x7FFF. On
+/// rumble cartridges bit 3 of the RAM bank register drives the motor
+/// instead, leaving eight RAM banks.
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ram_enabled = value == 0x0a,
+            0x2000..=0x2fff => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3fff => self.rom_bank = (self.rom_bank & 0xff) | (((value & 0x01) as u16) << 8),
+            0x4000..=0x5fff => {
+                if self.has_rumble {
+                    self.rumble = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0f;
+                }
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+
+        ram_index(ram, self.ram_bank as usize, addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        store_ram(ram, self.ram_bank as usize, addr, value)
+    }
+
+    fn reset(&mut self) {
+        *self = Mbc5::new(self.has_rumble);
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    #[test]
+    fn switches_nine_bit_rom_banks() {
+        let rom = numbered_rom(512);
+        let mut mbc = Mbc5::new(false);
+
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 1);
+
+        // bank 0 can be mapped high
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0);
+
+        mbc.write_rom(0x2000, 0x34);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x134);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0);
+
+        mbc.write_rom(0x2000, 0xff);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x7ffe), 0x1ff);
+    }
+
+    #[test]
+    fn ram_banks_and_enable() {
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(0x0000, 0x1a);
+        mbc.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[0], 0x00);
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_rom(0x4000, 0x0f);
+        mbc.write_ram(&mut ram, 0xa123, 0x42);
+        assert_eq!(ram[15 * 0x2000 + 0x123], 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xa123), 0x42);
+    }
+
+    #[test]
+    fn rumble_takes_ram_bank_bit_3() {
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mbc5::new(true);
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_rom(0x4000, 0x0b);
+        assert!(mbc.rumble());
+
+        mbc.write_ram(&mut ram, 0xa000, 0x42);
+        assert_eq!(ram[3 * 0x2000], 0x42);
+
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble());
+        assert!(!Mbc5::new(false).rumble());
+    }
+}
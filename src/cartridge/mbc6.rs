@@ -0,0 +1,316 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte };
+
+/// The Macronix MX29F008 flash chip on MBC6 boards, 1 MiB in 8 KiB banks.
+pub const MBC6_FLASH_SIZE: usize = 0x100000;
+
+const HALF_BANK_SIZE: usize = 0x2000;
+const RAM_HALF_BANK_SIZE: usize = 0x1000;
+const FLASH_SECTOR_SIZE: usize = 0x20000;
+
+/// Flash command sequence progress. Commands are unlocked by writing 0xAA
+/// to 0x5555 and 0x55 to 0x2AAA of the chip's address space.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FlashState {
+    Read,
+    Unlock1,
+    Unlock2,
+    Id,
+    Program,
+    EraseUnlock,
+    EraseUnlock1,
+    EraseUnlock2
+}
+
+/// MBC6, used only by Net de Get. The ROM, flash and RAM are split into
+/// two independently banked halves.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-03FF   | RAM enable, 0x0A enables                        |
+/// | 0400-07FF   | RAM bank for 0xA000-0xAFFF                      |
+/// | 0800-0BFF   | RAM bank for 0xB000-0xBFFF                      |
+/// | 0C00-0FFF   | flash enable                                    |
+/// | 1000        | flash write enable                              |
+/// | 2000-27FF   | 8 KiB bank for 0x4000-0x5FFF                    |
+/// | 2800-2FFF   | 0x08 maps flash at 0x4000-0x5FFF, else ROM      |
+/// | 3000-37FF   | 8 KiB bank for 0x6000-0x7FFF                    |
+/// | 3800-3FFF   | 0x08 maps flash at 0x6000-0x7FFF, else ROM      |
+pub struct Mbc6 {
+    ram_enabled: bool,
+    ram_banks: [u8; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    banks: [u8; 2],
+    flash_selected: [bool; 2],
+    flash: Vec<u8>,
+    flash_state: FlashState
+}
+
+impl Mbc6 {
+    pub fn new() -> Mbc6 {
+        Mbc6 {
+            ram_enabled: false,
+            ram_banks: [0; 2],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            banks: [0; 2],
+            flash_selected: [false; 2],
+            flash: vec![0xff; MBC6_FLASH_SIZE],
+            flash_state: FlashState::Read
+        }
+    }
+
+    fn window(addr: u16) -> usize {
+        ((addr - 0x4000) as usize) / HALF_BANK_SIZE
+    }
+
+    fn flash_addr(&self, addr: u16) -> usize {
+        let window = Mbc6::window(addr);
+        (self.banks[window] as usize * HALF_BANK_SIZE + (addr as usize & (HALF_BANK_SIZE - 1))) % MBC6_FLASH_SIZE
+    }
+
+    /// Steps the command sequence, returning whether the flash was written.
+    fn write_flash(&mut self, addr: u16, value: u8) -> bool {
+        let flash_addr = self.flash_addr(addr);
+        let command_addr = flash_addr & 0x7fff;
+        let mut written = false;
+
+        self.flash_state = match (self.flash_state, command_addr, value) {
+            (FlashState::Program, _, value) => {
+                // programming can only clear bits
+                if self.flash_write_enabled {
+                    self.flash[flash_addr] &= value;
+                    written = true;
+                }
+                FlashState::Read
+            },
+            // reset works from any state but programming, which takes any byte
+            (_, _, 0xf0) => FlashState::Read,
+            (FlashState::Read, 0x5555, 0xaa) | (FlashState::Id, 0x5555, 0xaa) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2aaa, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::Id,
+            (FlashState::Unlock2, 0x5555, 0xa0) => FlashState::Program,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::EraseUnlock,
+            (FlashState::EraseUnlock, 0x5555, 0xaa) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2aaa, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                if self.flash_write_enabled {
+                    self.flash.iter_mut().for_each(|byte| *byte = 0xff);
+                    written = true;
+                }
+                FlashState::Read
+            },
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                if self.flash_write_enabled {
+                    let sector = flash_addr / FLASH_SECTOR_SIZE * FLASH_SECTOR_SIZE;
+                    self.flash[sector..sector + FLASH_SECTOR_SIZE].iter_mut().for_each(|byte| *byte = 0xff);
+                    written = true;
+                }
+                FlashState::Read
+            },
+            _ => FlashState::Read
+        };
+
+        written
+    }
+}
+
+impl Default for Mbc6 {
+    fn default() -> Self {
+        Mbc6::new()
+    }
+}
+
+impl Mbc for Mbc6 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => {
+                let window = Mbc6::window(addr);
+
+                if self.flash_selected[window] {
+                    if !self.flash_enabled {
+                        return 0xff;
+                    }
+
+                    match self.flash_state {
+                        // manufacturer and device id
+                        FlashState::Id => if addr & 0x01 == 0 { 0xc2 } else { 0x81 },
+                        _ => self.flash[self.flash_addr(addr)]
+                    }
+                } else {
+                    // banks are half the usual size, so address in 8 KiB units
+                    let bank = self.banks[window] as usize;
+                    rom_byte(rom, bank / 2, ((bank % 2) * HALF_BANK_SIZE) as u16 | (addr & 0x1fff))
+                }
+            }
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x03ff => self.ram_enabled = value & 0x0f == 0x0a,
+            0x0400..=0x07ff => self.ram_banks[0] = value & 0x07,
+            0x0800..=0x0bff => self.ram_banks[1] = value & 0x07,
+            0x0c00..=0x0fff => self.flash_enabled = value & 0x01 != 0,
+            0x1000 => self.flash_write_enabled = value & 0x01 != 0,
+            0x2000..=0x27ff => self.banks[0] = value & 0x7f,
+            0x2800..=0x2fff => self.flash_selected[0] = value == 0x08,
+            0x3000..=0x37ff => self.banks[1] = value & 0x7f,
+            0x3800..=0x3fff => self.flash_selected[1] = value == 0x08,
+            0x4000..=0x7fff if self.flash_selected[Mbc6::window(addr)] && self.flash_enabled => {
+                return self.write_flash(addr, value);
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled || ram.is_empty() {
+            return 0xff;
+        }
+
+        let window = ((addr - 0xa000) as usize) / RAM_HALF_BANK_SIZE;
+        let index = self.ram_banks[window] as usize * RAM_HALF_BANK_SIZE + (addr as usize & (RAM_HALF_BANK_SIZE - 1));
+
+        ram[index % ram.len()]
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled || ram.is_empty() {
+            return false;
+        }
+
+        let window = ((addr - 0xa000) as usize) / RAM_HALF_BANK_SIZE;
+        let index = self.ram_banks[window] as usize * RAM_HALF_BANK_SIZE + (addr as usize & (RAM_HALF_BANK_SIZE - 1));
+        let len = ram.len();
+
+        ram[index % len] = value;
+        true
+    }
+
+    fn reset(&mut self) {
+        // flash is kept in place rather than reallocated by `new`
+        self.ram_enabled = false;
+        self.ram_banks = [0; 2];
+        self.flash_enabled = false;
+        self.flash_write_enabled = false;
+        self.banks = [0; 2];
+        self.flash_selected = [false; 2];
+        self.flash_state = FlashState::Read;
+    }
+
+    /// The flash is non-volatile, so it is saved along with the RAM.
+    fn save_state(&self) -> Vec<u8> {
+        self.flash.clone()
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        let len = data.len().min(MBC6_FLASH_SIZE);
+        self.flash[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    /// Writes `value` to `flash_addr` through the 0x4000 window.
+    fn write_flash_at(mbc: &mut Mbc6, flash_addr: usize, value: u8) {
+        mbc.write_rom(0x2000, (flash_addr / HALF_BANK_SIZE) as u8);
+        mbc.write_rom(0x4000 | (flash_addr % HALF_BANK_SIZE) as u16, value);
+    }
+
+    fn unlock(mbc: &mut Mbc6, command: u8) {
+        write_flash_at(mbc, 0x5555, 0xaa);
+        write_flash_at(mbc, 0x2aaa, 0x55);
+        write_flash_at(mbc, 0x5555, command);
+    }
+
+    fn flash_mbc6() -> Mbc6 {
+        let mut mbc = Mbc6::new();
+        mbc.write_rom(0x0c00, 0x01);
+        mbc.write_rom(0x1000, 0x01);
+        mbc.write_rom(0x2800, 0x08);
+        mbc
+    }
+
+    #[test]
+    fn banks_both_halves_in_8_kib_units() {
+        let rom = numbered_rom(8);
+        let mut mbc = Mbc6::new();
+
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x0e);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 2);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), rom[5 * HALF_BANK_SIZE]);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x6000), 7);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0);
+    }
+
+    #[test]
+    fn ram_halves_bank_separately() {
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc6::new();
+
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_rom(0x0400, 0x02);
+        mbc.write_rom(0x0800, 0x05);
+        mbc.write_ram(&mut ram, 0xa010, 0x11);
+        mbc.write_ram(&mut ram, 0xb010, 0x22);
+
+        assert_eq!(ram[2 * RAM_HALF_BANK_SIZE + 0x10], 0x11);
+        assert_eq!(ram[5 * RAM_HALF_BANK_SIZE + 0x10], 0x22);
+        assert_eq!(mbc.read_ram(&ram, 0xb010), 0x22);
+    }
+
+    #[test]
+    fn programs_and_erases_flash() {
+        let rom = numbered_rom(8);
+        let mut mbc = flash_mbc6();
+
+        unlock(&mut mbc, 0xa0);
+        write_flash_at(&mut mbc, 0x12345, 0x5a);
+
+        mbc.write_rom(0x2000, (0x12345 / HALF_BANK_SIZE) as u8);
+        assert_eq!(mbc.read_rom(&rom, 0x4000 | (0x12345 % HALF_BANK_SIZE) as u16), 0x5a);
+        assert_eq!(mbc.save_state()[0x12345], 0x5a);
+
+        // programming only clears bits
+        unlock(&mut mbc, 0xa0);
+        write_flash_at(&mut mbc, 0x12345, 0xf0);
+        assert_eq!(mbc.save_state()[0x12345], 0x50);
+
+        unlock(&mut mbc, 0x80);
+        write_flash_at(&mut mbc, 0x5555, 0xaa);
+        write_flash_at(&mut mbc, 0x2aaa, 0x55);
+        write_flash_at(&mut mbc, 0x12345, 0x30);
+        assert_eq!(mbc.save_state()[0x12345], 0xff);
+    }
+
+    #[test]
+    fn flash_is_read_only_without_write_enable() {
+        let mut mbc = flash_mbc6();
+        mbc.write_rom(0x1000, 0x00);
+
+        unlock(&mut mbc, 0xa0);
+        write_flash_at(&mut mbc, 0x0100, 0x00);
+        assert_eq!(mbc.save_state()[0x0100], 0xff);
+    }
+
+    #[test]
+    fn reports_the_chip_id() {
+        let rom = numbered_rom(8);
+        let mut mbc = flash_mbc6();
+
+        unlock(&mut mbc, 0x90);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0xc2);
+        assert_eq!(mbc.read_rom(&rom, 0x4001), 0x81);
+
+        write_flash_at(&mut mbc, 0x0000, 0xf0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0xff);
+    }
+}
@@ -0,0 +1,435 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte };
+
+/// MBC7 stores saves in a 93LC56 serial EEPROM, 128 words of 16 bits.
+pub const MBC7_EEPROM_SIZE: usize = 0x100;
+
+/// Supplies the tilt of the cartridge as (x, y) in units of g, positive
+/// x tilting right and positive y tilting towards the player.
+pub type TiltSource = Box<dyn FnMut() -> (f32, f32)>;
+
+const ACCELEROMETER_CENTER: u16 = 0x81d0;
+const ACCELEROMETER_ONE_G: f32 = 0x70 as f32;
+
+/// Where the EEPROM is in clocking a command in or data out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EepromState {
+    /// Shifting in the start bit, opcode and address.
+    Command,
+    /// Shifting a word out, MSB first.
+    Reading { word: u16, bits: u8 },
+    /// Shifting in a word for WRITE, or WRAL when `addr` is None.
+    Writing { addr: Option<u8>, word: u16, bits: u8 },
+    /// Finished, waiting for chip select to drop.
+    Done
+}
+
+/// The bit banged EEPROM at 0xA080, bit 7 is chip select, bit 6 the clock,
+/// bit 1 data in and bit 0 data out. Words are stored little endian.
+struct Eeprom {
+    select: bool,
+    clock: bool,
+    data_out: bool,
+    write_enabled: bool,
+    shift: u16,
+    bits: u8,
+    state: EepromState
+}
+
+impl Eeprom {
+    fn new() -> Eeprom {
+        Eeprom {
+            select: false,
+            clock: false,
+            data_out: true,
+            write_enabled: false,
+            shift: 0,
+            bits: 0,
+            state: EepromState::Command
+        }
+    }
+
+    fn read(&self) -> u8 {
+        ((self.select as u8) << 7) | ((self.clock as u8) << 6) | self.data_out as u8
+    }
+
+    fn write_word(storage: &mut [u8], addr: u8, word: u16) {
+        let index = (addr as usize & 0x7f) * 2;
+        storage[index..index + 2].copy_from_slice(&word.to_le_bytes());
+    }
+
+    /// Returns whether `storage` was written.
+    fn write(&mut self, storage: &mut [u8], value: u8) -> bool {
+        let select = value & 0x80 != 0;
+        let clock = value & 0x40 != 0;
+        let data_in = (value & 0x02 != 0) as u16;
+
+        if !select {
+            self.select = false;
+            self.clock = clock;
+            self.shift = 0;
+            self.bits = 0;
+            self.state = EepromState::Command;
+            return false;
+        }
+
+        let rising = clock && !self.clock;
+        self.select = true;
+        self.clock = clock;
+
+        if !rising {
+            return false;
+        }
+
+        match self.state {
+            EepromState::Command => {
+                // leading zeros before the start bit are ignored
+                if self.bits == 0 && data_in == 0 {
+                    return false;
+                }
+
+                self.shift = (self.shift << 1) | data_in;
+                self.bits += 1;
+
+                // start bit, two opcode bits and eight address bits
+                if self.bits == 11 {
+                    return self.run_command(storage);
+                }
+            },
+            EepromState::Reading { word, bits } => {
+                self.data_out = word & (0x8000 >> bits) != 0;
+
+                self.state = if bits == 15 {
+                    EepromState::Done
+                } else {
+                    EepromState::Reading { word, bits: bits + 1 }
+                };
+            },
+            EepromState::Writing { addr, word, bits } => {
+                let word = (word << 1) | data_in;
+
+                if bits < 15 {
+                    self.state = EepromState::Writing { addr, word, bits: bits + 1 };
+                    return false;
+                }
+
+                match addr {
+                    Some(addr) => Eeprom::write_word(storage, addr, word),
+                    None => (0..0x80).for_each(|addr| Eeprom::write_word(storage, addr, word))
+                }
+
+                self.data_out = true;
+                self.state = EepromState::Done;
+                return true;
+            },
+            EepromState::Done => {}
+        }
+
+        false
+    }
+
+    /// Returns whether `storage` was written, as ERASE and ERAL do straight away.
+    fn run_command(&mut self, storage: &mut [u8]) -> bool {
+        let opcode = (self.shift >> 8) & 0x03;
+        let addr = (self.shift & 0xff) as u8;
+
+        self.state = EepromState::Done;
+
+        match opcode {
+            // READ, a dummy zero bit comes out first
+            0b10 => {
+                let index = (addr as usize & 0x7f) * 2;
+                let word = u16::from_le_bytes([storage[index], storage[index + 1]]);
+
+                self.data_out = false;
+                self.state = EepromState::Reading { word, bits: 0 };
+            },
+            // WRITE
+            0b01 if self.write_enabled => {
+                self.state = EepromState::Writing { addr: Some(addr), word: 0, bits: 0 };
+            },
+            // ERASE
+            0b11 if self.write_enabled => {
+                Eeprom::write_word(storage, addr, 0xffff);
+                self.data_out = true;
+                return true;
+            },
+            0b00 => match addr >> 6 {
+                // EWDS
+                0b00 => self.write_enabled = false,
+                // WRAL
+                0b01 if self.write_enabled => {
+                    self.state = EepromState::Writing { addr: None, word: 0, bits: 0 };
+                },
+                // ERAL
+                0b10 if self.write_enabled => {
+                    storage.iter_mut().for_each(|byte| *byte = 0xff);
+                    self.data_out = true;
+                    return true;
+                },
+                // EWEN
+                0b11 => self.write_enabled = true,
+                _ => {}
+            },
+            _ => {}
+        }
+
+        false
+    }
+}
+
+/// MBC7, with an accelerometer and an EEPROM in place of RAM.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | first RAM enable, 0x0A enables                  |
+/// | 2000-3FFF   | seven bit ROM bank                              |
+/// | 4000-5FFF   | second RAM enable, 0x40 enables                 |
+///
+/// With both enables set 0xA000-0xAFFF holds the sensor registers, picked
+/// by address bits 4-7:
+///
+/// | register | function                                              |
+/// |----------|-------------------------------------------------------|
+/// | Ax0x     | write 0x55 to reset the latched values                |
+/// | Ax1x     | write 0xAA after the reset to latch the accelerometer |
+/// | Ax2x-Ax5x| X low, X high, Y low, Y high                          |
+/// | Ax6x     | always 0x00                                           |
+/// | Ax7x     | always 0xFF                                           |
+/// | Ax8x     | EEPROM                                                |
+pub struct Mbc7 {
+    ram_enabled: bool,
+    sensor_enabled: bool,
+    rom_bank: u8,
+    latch_ready: bool,
+    x: u16,
+    y: u16,
+    eeprom: Eeprom,
+    tilt_source: Option<TiltSource>
+}
+
+impl Mbc7 {
+    pub fn new() -> Mbc7 {
+        Mbc7 {
+            ram_enabled: false,
+            sensor_enabled: false,
+            rom_bank: 1,
+            latch_ready: false,
+            x: 0x8000,
+            y: 0x8000,
+            eeprom: Eeprom::new(),
+            tilt_source: None
+        }
+    }
+
+    fn latch(&mut self) {
+        let (x, y) = self.tilt_source.as_mut().map_or((0.0, 0.0), |source| source());
+
+        let scale = |g: f32| {
+            (ACCELEROMETER_CENTER as f32 + g * ACCELEROMETER_ONE_G).round().max(0.0).min(u16::MAX as f32) as u16
+        };
+
+        self.x = scale(-x);
+        self.y = scale(y);
+    }
+}
+
+impl Default for Mbc7 {
+    fn default() -> Self {
+        Mbc7::new()
+    }
+}
+
+impl Mbc for Mbc7 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => self.ram_enabled = value == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            0x4000..=0x5fff => self.sensor_enabled = value == 0x40,
+            _ => {}
+        }
+
+        false
+    }
+
+    fn read_ram(&self, _ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled || !self.sensor_enabled || addr >= 0xb000 {
+            return 0xff;
+        }
+
+        match (addr >> 4) & 0x0f {
+            0x2 => (self.x & 0xff) as u8,
+            0x3 => (self.x >> 8) as u8,
+            0x4 => (self.y & 0xff) as u8,
+            0x5 => (self.y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xff
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled || !self.sensor_enabled || addr >= 0xb000 {
+            return false;
+        }
+
+        match (addr >> 4) & 0x0f {
+            0x0 if value == 0x55 => {
+                self.x = 0x8000;
+                self.y = 0x8000;
+                self.latch_ready = true;
+            },
+            0x1 if value == 0xaa && self.latch_ready => {
+                self.latch();
+                self.latch_ready = false;
+            },
+            0x8 if ram.len() >= MBC7_EEPROM_SIZE => return self.eeprom.write(ram, value),
+            _ => {}
+        }
+
+        false
+    }
+
+    fn reset(&mut self) {
+        *self = Mbc7 { tilt_source: self.tilt_source.take(), ..Mbc7::new() };
+    }
+
+    fn set_tilt_source(&mut self, source: TiltSource) {
+        self.tilt_source = Some(source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECT: u8 = 0x80;
+    const CLOCK: u8 = 0x40;
+    const DATA_IN: u8 = 0x02;
+
+    fn enabled_mbc7() -> Mbc7 {
+        let mut mbc = Mbc7::new();
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    /// Clocks one bit into the EEPROM and returns what it drives out after
+    /// the rising edge.
+    fn clock_bit(mbc: &mut Mbc7, ram: &mut [u8], bit: bool) -> bool {
+        let data = if bit { DATA_IN } else { 0 };
+
+        mbc.write_ram(ram, 0xa080, SELECT | data);
+        mbc.write_ram(ram, 0xa080, SELECT | CLOCK | data);
+        mbc.read_ram(ram, 0xa080) & 0x01 != 0
+    }
+
+    fn send(mbc: &mut Mbc7, ram: &mut [u8], value: u32, bits: u8) {
+        for bit in (0..bits).rev() {
+            clock_bit(mbc, ram, value & (1 << bit) != 0);
+        }
+    }
+
+    fn command(mbc: &mut Mbc7, ram: &mut [u8], opcode: u32, addr: u8) {
+        send(mbc, ram, 0b100 | opcode, 3);
+        send(mbc, ram, addr as u32, 8);
+    }
+
+    fn deselect(mbc: &mut Mbc7, ram: &mut [u8]) {
+        mbc.write_ram(ram, 0xa080, 0x00);
+    }
+
+    fn read_word(mbc: &mut Mbc7, ram: &mut [u8], addr: u8) -> u16 {
+        command(mbc, ram, 0b10, addr);
+
+        // a dummy zero comes out before the data
+        assert_eq!(mbc.read_ram(ram, 0xa080) & 0x01, 0);
+        let word = (0..16).fold(0, |word, _| (word << 1) | clock_bit(mbc, ram, false) as u16);
+
+        deselect(mbc, ram);
+        word
+    }
+
+    #[test]
+    fn eeprom_write_and_read() {
+        let mut ram = vec![0xff; MBC7_EEPROM_SIZE];
+        let mut mbc = enabled_mbc7();
+
+        // writes are ignored until EWEN
+        command(&mut mbc, &mut ram, 0b01, 0x12);
+        send(&mut mbc, &mut ram, 0x1234, 16);
+        deselect(&mut mbc, &mut ram);
+        assert_eq!(ram[0x24..0x26], [0xff, 0xff]);
+
+        command(&mut mbc, &mut ram, 0b00, 0xc0);
+        deselect(&mut mbc, &mut ram);
+
+        command(&mut mbc, &mut ram, 0b01, 0x12);
+        send(&mut mbc, &mut ram, 0xbeef, 16);
+        assert_eq!(mbc.read_ram(&ram, 0xa080) & 0x01, 1);
+        deselect(&mut mbc, &mut ram);
+
+        assert_eq!(ram[0x24..0x26], [0xef, 0xbe]);
+        assert_eq!(read_word(&mut mbc, &mut ram, 0x12), 0xbeef);
+
+        // ERASE, then EWDS locks it again
+        command(&mut mbc, &mut ram, 0b11, 0x12);
+        deselect(&mut mbc, &mut ram);
+        assert_eq!(read_word(&mut mbc, &mut ram, 0x12), 0xffff);
+
+        command(&mut mbc, &mut ram, 0b00, 0x00);
+        deselect(&mut mbc, &mut ram);
+        command(&mut mbc, &mut ram, 0b01, 0x12);
+        send(&mut mbc, &mut ram, 0x0000, 16);
+        deselect(&mut mbc, &mut ram);
+        assert_eq!(read_word(&mut mbc, &mut ram, 0x12), 0xffff);
+    }
+
+    #[test]
+    fn eeprom_write_all() {
+        let mut ram = vec![0xff; MBC7_EEPROM_SIZE];
+        let mut mbc = enabled_mbc7();
+
+        command(&mut mbc, &mut ram, 0b00, 0xc0);
+        deselect(&mut mbc, &mut ram);
+
+        command(&mut mbc, &mut ram, 0b00, 0x40);
+        send(&mut mbc, &mut ram, 0x1234, 16);
+        deselect(&mut mbc, &mut ram);
+
+        assert!(ram.chunks(2).all(|word| word == [0x34, 0x12]));
+    }
+
+    #[test]
+    fn latches_the_tilt() {
+        let mut ram = vec![0xff; MBC7_EEPROM_SIZE];
+        let mut mbc = enabled_mbc7();
+        mbc.set_tilt_source(Box::new(|| (0.5, -0.25)));
+
+        let read_axes = |mbc: &Mbc7, ram: &[u8]| {
+            let x = u16::from_le_bytes([mbc.read_ram(ram, 0xa020), mbc.read_ram(ram, 0xa030)]);
+            let y = u16::from_le_bytes([mbc.read_ram(ram, 0xa040), mbc.read_ram(ram, 0xa050)]);
+            (x, y)
+        };
+
+        // latching needs the reset first
+        mbc.write_ram(&mut ram, 0xa010, 0xaa);
+        assert_eq!(read_axes(&mbc, &ram), (0x8000, 0x8000));
+
+        mbc.write_ram(&mut ram, 0xa000, 0x55);
+        mbc.write_ram(&mut ram, 0xa010, 0xaa);
+        assert_eq!(read_axes(&mbc, &ram), (0x81d0 - 0x38, 0x81d0 - 0x1c));
+
+        assert_eq!(mbc.read_ram(&ram, 0xa060), 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa070), 0xff);
+
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xa020), 0xff);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::cartridge::mbc::{ Mbc, rom_byte, ram_index, store_ram };
+
+/// MMM01, a multicart mapper that starts out showing the menu in the last
+/// 32 KiB of ROM. The menu picks a game by writing the outer bank bits and
+/// then setting the map bit, after which the outer bits are locked and the
+/// game sees what looks like an MBC1.
+///
+/// | range       | register                                        |
+/// |-------------|-------------------------------------------------|
+/// | 0000-1FFF   | RAM enable in bits 0-3, bit 6 maps the game     |
+/// | 2000-3FFF   | ROM bank bits 0-4, bits 5-6 while unmapped      |
+/// | 4000-5FFF   | RAM bank bits 0-1, while unmapped also RAM bank |
+/// |             | bits 2-3 and ROM bank bits 7-8 in bits 4-5      |
+/// | 6000-7FFF   | banking mode in bit 0, while unmapped bits 2-5  |
+/// |             | mask ROM bank bits 1-4 from the game            |
+pub struct Mmm01 {
+    mapped: bool,
+    ram_enabled: bool,
+    rom_low: u8,
+    rom_mid: u8,
+    rom_high: u8,
+    rom_mask: u8,
+    ram_low: u8,
+    ram_high: u8,
+    mode: bool
+}
+
+impl Mmm01 {
+    pub fn new() -> Mmm01 {
+        Mmm01 {
+            mapped: false,
+            ram_enabled: false,
+            rom_low: 0,
+            rom_mid: 0,
+            rom_high: 0,
+            rom_mask: 0,
+            ram_low: 0,
+            ram_high: 0,
+            mode: false
+        }
+    }
+
+    /// Low bank bits owned by the menu rather than the game.
+    fn locked_low_bits(&self) -> u8 {
+        self.rom_mask << 1
+    }
+
+    fn outer_bank(&self) -> usize {
+        ((self.rom_high as usize) << 7)
+            | ((self.rom_mid as usize) << 5)
+            | (self.rom_low & self.locked_low_bits()) as usize
+    }
+
+    fn zero_bank(&self) -> usize {
+        if self.mapped { self.outer_bank() } else { 0x1fe }
+    }
+
+    fn high_bank(&self) -> usize {
+        if !self.mapped {
+            return 0x1ff;
+        }
+
+        let mut low = self.rom_low & !self.locked_low_bits() & 0x1f;
+        if low == 0 {
+            low = 1;
+        }
+
+        self.outer_bank() | low as usize
+    }
+
+    /// Like MBC1 the low RAM bank bits only apply in the advanced mode.
+    fn ram_bank(&self) -> usize {
+        let low = if self.mode { self.ram_low } else { 0 };
+        ((self.ram_high << 2) | low) as usize
+    }
+}
+
+impl Default for Mmm01 {
+    fn default() -> Self {
+        Mmm01::new()
+    }
+}
+
+impl Mbc for Mmm01 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => rom_byte(rom, self.zero_bank(), addr),
+            _ => rom_byte(rom, self.high_bank(), addr)
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            0x0000..=0x1fff => {
+                self.ram_enabled = value & 0x0f == 0x0a;
+                if !self.mapped {
+                    self.mapped = value & 0x40 != 0;
+                }
+            },
+            0x2000..=0x3fff => {
+                let locked = if self.mapped { self.locked_low_bits() } else { 0 };
+                self.rom_low = (self.rom_low & locked) | (value & 0x1f & !locked);
+
+                if !self.mapped {
+                    self.rom_mid = (value >> 5) & 0x03;
+                }
+            },
+            0x4000..=0x5fff => {
+                self.ram_low = value & 0x03;
+
+                if !self.mapped {
+                    self.ram_high = (value >> 2) & 0x03;
+                    self.rom_high = (value >> 4) & 0x03;
+                }
+            },
+            _ => {
+                self.mode = value & 0x01 != 0;
+
+                if !self.mapped {
+                    self.rom_mask = (value >> 2) & 0x0f;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+
+        ram_index(ram, self.ram_bank(), addr).map_or(0xff, |index| ram[index])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        store_ram(ram, self.ram_bank(), addr, value)
+    }
+
+    fn reset(&mut self) {
+        *self = Mmm01::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::{ mapped_bank, numbered_rom };
+
+    #[test]
+    fn starts_on_the_menu_in_the_last_banks() {
+        let rom = numbered_rom(128);
+        let mbc = Mmm01::new();
+
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 126);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 127);
+    }
+
+    #[test]
+    fn menu_selects_and_locks_a_game() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mmm01::new();
+
+        // the game starts at bank 0x20
+        mbc.write_rom(0x2000, 0x20);
+        mbc.write_rom(0x0000, 0x40);
+
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0x20);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x21);
+
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x23);
+
+        // the outer bits can't be changed once mapped
+        mbc.write_rom(0x2000, 0x45);
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x25);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0x20);
+    }
+
+    #[test]
+    fn mask_hands_low_bits_to_the_menu() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mmm01::new();
+
+        // a 64 KiB game at bank 0x08, 0x38 locks bank bits 2-4 and leaves
+        // the game bits 1 and 0
+        mbc.write_rom(0x2000, 0x08);
+        mbc.write_rom(0x6000, 0x38);
+        mbc.write_rom(0x0000, 0x40);
+
+        assert_eq!(mapped_bank(&mbc, &rom, 0x0000), 0x08);
+        mbc.write_rom(0x2000, 0x01);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x09);
+        mbc.write_rom(0x2000, 0x1f);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x0b);
+    }
+
+    #[test]
+    fn full_mask_leaves_the_game_one_bit() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mmm01::new();
+
+        // a 32 KiB game at bank 0x08, 0x3c locks bank bits 1-4
+        mbc.write_rom(0x2000, 0x08);
+        mbc.write_rom(0x6000, 0x3c);
+        mbc.write_rom(0x0000, 0x40);
+
+        mbc.write_rom(0x2000, 0x1f);
+        assert_eq!(mapped_bank(&mbc, &rom, 0x4000), 0x09);
+    }
+}
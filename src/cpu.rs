@@ -1,10 +1,12 @@
 
 use std::fmt;
 
-mod registers;
-mod opcodes;
+use crate::mmu::Bus;
 
-// L/H registers reversed, c9 instruction lands on wrong addr 49922 instead of 1113 (0x459)
+pub mod registers;
+mod opcodes;
+mod prefixed;
+pub mod interrupts;
 
 pub struct CPU {
     pub a: u8,
@@ -21,7 +23,13 @@ pub struct CPU {
     pub ime: u8,
     pub sp: u16,
 
-    stopped: bool
+    stopped: bool,
+    /// Cycles left of the pause while a CGB speed switch settles.
+    speed_switch_cycles: u32,
+    halted: bool,
+    halt_bug: bool,
+    locked: bool,
+    ei_delay: u8
 }
 
 fn make_u16(lo: u8, hi:u8) -> u16 {
@@ -45,6 +53,12 @@ fn unmake_u16(value: u16) -> (u8, u8) {
     (lo, hi)
 }
 
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
+}
+
 impl CPU {
     pub fn new() -> CPU {
         CPU { 
@@ -58,52 +72,75 @@ impl CPU {
 
             ime: 0,
             sp: 0xfffe,
-            stopped: false
+            stopped: false,
+            speed_switch_cycles: 0,
+            halted: false,
+            halt_bug: false,
+            locked: false,
+            ei_delay: 0
         }
     }
 
-    pub fn is_stopped(&mut self) -> bool {
+    /// True in STOP, which ends only when a button press pulls a selected
+    /// joypad line low, see `Bus::take_joypad_press`.
+    pub fn is_stopped(&self) -> bool {
         self.stopped
     }
 
-    pub fn fetch(&mut self, data: &Vec<u8>) -> u8 {
-        let result = data[self.pc as usize];
-        self.pc += 1;
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// True once an illegal opcode has been executed. Real hardware hangs
+    /// until it is power cycled, so nothing short of a reset clears this.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn fetch<B: Bus>(&mut self, bus: &B) -> u8 {
+        let result = bus.read(self.pc);
+
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.pc = self.pc.wrapping_add(1);
+        }
+
         result
     }
 
-    pub fn fetch_u16(&mut self, data: &Vec<u8>) -> u16 {
-        let lo = self.fetch(data);
-        let hi = self.fetch(data);
+    pub fn fetch_u16<B: Bus>(&mut self, bus: &B) -> u16 {
+        let lo = self.fetch(bus);
+        let hi = self.fetch(bus);
 
-        return make_u16(lo, hi);
+        make_u16(lo, hi)
     }
 
-    pub fn read(&self, addr: u16, data: &Vec<u8>) -> u8 {
-        let ret = data[addr as usize];
-        println!("read {:#08x} from {:#08x}", ret, addr);
+    pub fn read<B: Bus>(&self, addr: u16, bus: &B) -> u8 {
+        let ret = bus.read(addr);
+        trace!("read {:#08x} from {:#08x}", ret, addr);
         ret
     }
 
-    pub fn read_u16(&self, addr: u16, data: &Vec<u8>) -> u16 {
-        let lo = data[addr as usize];
-        let hi = data[(addr+1) as usize];
+    pub fn read_u16<B: Bus>(&self, addr: u16, bus: &B) -> u16 {
+        let lo = bus.read(addr);
+        let hi = bus.read(addr.wrapping_add(1));
 
         let ret = make_u16(lo, hi);
-        println!("read {:#08x} from {:#08x}", ret, addr);
+        trace!("read {:#08x} from {:#08x}", ret, addr);
         ret
     }
 
     
-    pub fn store(&self, addr: u16, value: u8, data: &mut Vec<u8>){
-        data[addr as usize] = value;
+    pub fn store<B: Bus>(&self, addr: u16, value: u8, bus: &mut B){
+        bus.write(addr, value);
     }
 
-    pub fn store_u16(&self, addr: u16, value: u16, data: &mut Vec<u8>){
+    pub fn store_u16<B: Bus>(&self, addr: u16, value: u16, bus: &mut B){
         let (lo, hi) = unmake_u16(value);
 
-        data[addr as usize] = lo;
-        data[addr as usize + 1] = hi;
+        bus.write(addr, lo);
+        bus.write(addr.wrapping_add(1), hi);
     }
 }
 
@@ -118,45 +155,47 @@ impl fmt::Display for CPU {
     }
 }
 
+
 impl CPU {
     pub fn get_af(&self) -> u16 {
-        make_u16(self.a, self.f)
+        make_u16(self.f, self.a)
     }
 
     pub fn get_hl(&self) -> u16 {
-        make_u16(self.h, self.l)
+        make_u16(self.l, self.h)
     }
 
     pub fn get_bc(&self) -> u16 {
-        make_u16(self.b, self.c)
+        make_u16(self.c, self.b)
     }
 
     pub fn get_de(&self) -> u16 {
-        make_u16(self.d, self.e)
+        make_u16(self.e, self.d)
     }
 
     pub fn set_af(&mut self, value: u16) {
         let (lo, hi) = unmake_u16(value);
-        self.a = lo;
-        self.f = hi;
+        self.a = hi;
+        // the low nibble of F is hardwired to zero
+        self.f = lo & 0xf0;
     }
 
     pub fn set_hl(&mut self, value: u16) {
         let (lo, hi) = unmake_u16(value);
-        self.h = lo;
-        self.l = hi;
+        self.h = hi;
+        self.l = lo;
     }
 
     pub fn set_bc(&mut self, value: u16) {
         let (lo, hi) = unmake_u16(value);
-        self.b = lo;
-        self.c = hi;
+        self.b = hi;
+        self.c = lo;
     }
 
     pub fn set_de(&mut self, value: u16) {
         let (lo, hi) = unmake_u16(value);
-        self.d = lo;
-        self.e = hi;
+        self.d = hi;
+        self.e = lo;
     }
 
     pub const FLAG_ZERO: u8 = 1 << 0x07;
@@ -174,55 +213,95 @@ impl CPU {
         self.f &= !flag;
     }
 
+    #[inline(always)]
+    pub fn write_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.set_flag(flag);
+        } else {
+            self.unset_flag(flag);
+        }
+    }
+
     pub fn read_flag(&self, flag: u8) -> bool {
         (self.f & flag) > 0
     }
 
     pub fn alu_add(&mut self, a: u8, b: u8) -> u8 {
-        
-        self.unset_flag(CPU::FLAG_HALF_CARRY | CPU::FLAG_CARRY | CPU::FLAG_SUBTRACT | CPU::FLAG_ZERO);
+        self.alu_adc(a, b, false)
+    }
 
-        let result: u16 = (a as u16) + (b as u16);
+    pub fn alu_adc(&mut self, a: u8, b: u8, carry_in: bool) -> u8 {
+        let carry = carry_in as u16;
+        let result: u16 = (a as u16) + (b as u16) + carry;
 
-        if (((a & 0x0f) + (b & 0x0f)) & 0xf0) > 0 {
-            self.set_flag(CPU::FLAG_HALF_CARRY);
-        }
-
-        if result & 0x0100 == 0x0100 {
-            self.set_flag(CPU::FLAG_CARRY);
-        }
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result & 0xff == 0);
+        self.write_flag(CPU::FLAG_HALF_CARRY, (a & 0x0f) as u16 + (b & 0x0f) as u16 + carry > 0x0f);
+        self.write_flag(CPU::FLAG_CARRY, result > 0xff);
 
-        if self.a == 0 {
-            self.set_flag(CPU::FLAG_ZERO);
-        }
-        
         (result & 0xff) as u8
     }
 
     pub fn alu_sub(&mut self, a: u8, b: u8) -> u8 {
-        let twos_compliment = !b + 1;
-        
-        let result = self.alu_add(a, twos_compliment);
-        self.set_flag(CPU::FLAG_SUBTRACT);
+        self.alu_sbc(a, b, false)
+    }
+
+    pub fn alu_sbc(&mut self, a: u8, b: u8, carry_in: bool) -> u8 {
+        let carry = carry_in as i16;
+        let result: i16 = (a as i16) - (b as i16) - carry;
+
+        self.f = CPU::FLAG_SUBTRACT;
+        self.write_flag(CPU::FLAG_ZERO, result & 0xff == 0);
+        self.write_flag(CPU::FLAG_HALF_CARRY, ((a & 0x0f) as i16) - ((b & 0x0f) as i16) - carry < 0);
+        self.write_flag(CPU::FLAG_CARRY, result < 0);
+
+        (result & 0xff) as u8
+    }
+
+    pub fn alu_and(&mut self, a: u8, b: u8) -> u8 {
+        let result = a & b;
+        self.f = CPU::FLAG_HALF_CARRY;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
         result
     }
 
+    pub fn alu_xor(&mut self, a: u8, b: u8) -> u8 {
+        let result = a ^ b;
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        result
+    }
+
+    pub fn alu_or(&mut self, a: u8, b: u8) -> u8 {
+        let result = a | b;
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        result
+    }
+
+    /// Compare is a subtraction that only keeps the flags.
+    pub fn alu_cp(&mut self, a: u8, b: u8) {
+        self.alu_sub(a, b);
+    }
+
     pub fn alu_inc(&mut self, a: u8) -> u8 {
-        let result = self.alu_add(a, 1);
+        let result = a.wrapping_add(1);
 
-        if result == 0 {
-            self.set_flag(CPU::FLAG_ZERO);
-        }
+        // carry is left untouched
+        self.unset_flag(CPU::FLAG_SUBTRACT);
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_HALF_CARRY, a & 0x0f == 0x0f);
 
         result
     }
 
     pub fn alu_dec(&mut self, a: u8) -> u8 {
-        let result = self.alu_sub(a, 1);
-        
-        if result == 0 {
-            self.set_flag(CPU::FLAG_ZERO);
-        }
+        let result = a.wrapping_sub(1);
+
+        // carry is left untouched
+        self.set_flag(CPU::FLAG_SUBTRACT);
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_HALF_CARRY, a & 0x0f == 0x00);
 
         result
     }
@@ -234,4 +313,127 @@ impl CPU {
     pub fn alu_dec_u16(&self, a: u16) -> u16 {
         a.wrapping_sub(1)
     }
-}
\ No newline at end of file
+
+    /// ADD HL, rr: zero is left untouched, carries come from bits 11 and 15.
+    pub fn alu_add_u16(&mut self, a: u16, b: u16) -> u16 {
+        let result = (a as u32) + (b as u32);
+
+        self.unset_flag(CPU::FLAG_SUBTRACT);
+        self.write_flag(CPU::FLAG_HALF_CARRY, (a & 0x0fff) + (b & 0x0fff) > 0x0fff);
+        self.write_flag(CPU::FLAG_CARRY, result > 0xffff);
+
+        (result & 0xffff) as u16
+    }
+
+    /// SP + e8 as used by ADD SP, e8 and LD HL, SP+e8. The flags are computed
+    /// from an unsigned add on the low byte, zero and subtract are cleared.
+    pub fn alu_add_sp(&mut self, sp: u16, offset: u8) -> u16 {
+        let signed = offset as i8 as i16 as u16;
+
+        self.f = 0;
+        self.write_flag(CPU::FLAG_HALF_CARRY, (sp & 0x000f) + (offset as u16 & 0x000f) > 0x000f);
+        self.write_flag(CPU::FLAG_CARRY, (sp & 0x00ff) + (offset as u16) > 0x00ff);
+
+        sp.wrapping_add(signed)
+    }
+
+    pub fn alu_rlc(&mut self, a: u8) -> u8 {
+        let result = a.rotate_left(1);
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x80 != 0);
+        result
+    }
+
+    pub fn alu_rrc(&mut self, a: u8) -> u8 {
+        let result = a.rotate_right(1);
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x01 != 0);
+        result
+    }
+
+    pub fn alu_rl(&mut self, a: u8) -> u8 {
+        let result = (a << 1) | self.read_flag(CPU::FLAG_CARRY) as u8;
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x80 != 0);
+        result
+    }
+
+    pub fn alu_rr(&mut self, a: u8) -> u8 {
+        let result = (a >> 1) | ((self.read_flag(CPU::FLAG_CARRY) as u8) << 7);
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x01 != 0);
+        result
+    }
+
+    pub fn alu_sla(&mut self, a: u8) -> u8 {
+        let result = a << 1;
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x80 != 0);
+        result
+    }
+
+    /// Arithmetic shift right, bit 7 is kept.
+    pub fn alu_sra(&mut self, a: u8) -> u8 {
+        let result = (a >> 1) | (a & 0x80);
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x01 != 0);
+        result
+    }
+
+    pub fn alu_swap(&mut self, a: u8) -> u8 {
+        let result = a.rotate_left(4);
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        result
+    }
+
+    pub fn alu_srl(&mut self, a: u8) -> u8 {
+        let result = a >> 1;
+        self.f = 0;
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, a & 0x01 != 0);
+        result
+    }
+
+    /// BIT n: zero is set when the bit is clear, carry is left untouched.
+    pub fn alu_bit(&mut self, bit: u8, a: u8) {
+        self.unset_flag(CPU::FLAG_SUBTRACT);
+        self.set_flag(CPU::FLAG_HALF_CARRY);
+        self.write_flag(CPU::FLAG_ZERO, a & (1 << bit) == 0);
+    }
+
+    /// Decimal adjust A after a BCD add or subtract.
+    pub fn alu_daa(&mut self, a: u8) -> u8 {
+        let mut result = a;
+        let mut carry = self.read_flag(CPU::FLAG_CARRY);
+
+        if self.read_flag(CPU::FLAG_SUBTRACT) {
+            if carry {
+                result = result.wrapping_sub(0x60);
+            }
+            if self.read_flag(CPU::FLAG_HALF_CARRY) {
+                result = result.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                result = result.wrapping_add(0x60);
+                carry = true;
+            }
+            if self.read_flag(CPU::FLAG_HALF_CARRY) || (a & 0x0f) > 0x09 {
+                result = result.wrapping_add(0x06);
+            }
+        }
+
+        self.unset_flag(CPU::FLAG_HALF_CARRY);
+        self.write_flag(CPU::FLAG_ZERO, result == 0);
+        self.write_flag(CPU::FLAG_CARRY, carry);
+
+        result
+    }
+}
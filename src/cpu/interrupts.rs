@@ -0,0 +1,215 @@
+use crate::cpu::CPU;
+use crate::mmu::Bus;
+
+pub const IF_ADDR: u16 = 0xff0f;
+pub const IE_ADDR: u16 = 0xffff;
+
+/// Interrupt sources in priority order, the lowest bit wins when several are
+/// pending at once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank, LcdStat, Timer, Serial, Joypad
+}
+
+impl Interrupt {
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank, Interrupt::LcdStat, Interrupt::Timer, Interrupt::Serial, Interrupt::Joypad
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    pub fn vector(self) -> u16 {
+        0x40 + 0x08 * (self as u16)
+    }
+}
+
+impl CPU {
+    /// Sets the interrupt's bit in IF, it is serviced once IE and IME allow it.
+    pub fn request_interrupt<B: Bus>(&self, interrupt: Interrupt, bus: &mut B) {
+        let flags = self.read(IF_ADDR, bus);
+        self.store(IF_ADDR, flags | interrupt.mask(), bus);
+    }
+
+    fn pending_interrupts<B: Bus>(&self, bus: &B) -> u8 {
+        self.read(IF_ADDR, bus) & self.read(IE_ADDR, bus) & 0x1f
+    }
+
+    /// Wakes the CPU from HALT when an interrupt is pending and dispatches
+    /// the highest priority one if IME is set. Returns true when the
+    /// dispatch used up this step.
+    pub(super) fn service_interrupts<B: Bus>(&mut self, bus: &mut B) -> bool {
+        let pending = self.pending_interrupts(bus);
+
+        if pending == 0 {
+            return false;
+        }
+
+        // HALT ends on any pending interrupt, even with IME cleared
+        self.halted = false;
+
+        if self.ime == 0 {
+            return false;
+        }
+
+        let interrupt = Interrupt::ALL.iter()
+            .copied()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+            .unwrap();
+
+        trace!("servicing interrupt {:?}", interrupt);
+
+        self.ime = 0;
+        self.ei_delay = 0;
+
+        let flags = self.read(IF_ADDR, bus);
+        self.store(IF_ADDR, flags & !interrupt.mask(), bus);
+
+        self.sp = self.sp.wrapping_sub(2);
+        self.store_u16(self.sp, self.pc, bus);
+        self.pc = interrupt.vector();
+
+        // two wait states, the push and the jump
+        self.cycle_delay = 20;
+        true
+    }
+
+    /// HALT either stops the CPU until an interrupt is pending or, with IME
+    /// cleared and an interrupt already pending, triggers the HALT bug: the
+    /// CPU keeps running but fails to increment PC after the next fetch.
+    pub(super) fn halt<B: Bus>(&mut self, bus: &B) {
+        if self.ime == 0 && self.pending_interrupts(bus) != 0 {
+            trace!("HALT bug");
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+    }
+
+    /// EI only takes effect after the instruction that follows it.
+    pub(super) fn enable_interrupts_delayed(&mut self) {
+        self.ei_delay = 2;
+    }
+
+    pub(super) fn tick_ime_delay(&mut self) {
+        if self.ei_delay > 0 {
+            self.ei_delay -= 1;
+
+            if self.ei_delay == 0 {
+                self.ime = 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM_ADDR: u16 = 0x0100;
+
+    /// 64 KiB of plain RAM, so IF and IE read back exactly what was written.
+    struct Ram(Vec<u8>);
+
+    impl Bus for Ram {
+        fn read(&self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.0[addr as usize] = value;
+        }
+    }
+
+    /// A CPU about to run `program`, with `enabled` in IE and `requested`
+    /// in IF.
+    fn load(program: &[u8], enabled: u8, requested: u8) -> (CPU, Ram) {
+        let mut ram = Ram(vec![0; 0x10000]);
+        ram.0[PROGRAM_ADDR as usize..PROGRAM_ADDR as usize + program.len()].copy_from_slice(program);
+        ram.0[IE_ADDR as usize] = enabled;
+        ram.0[IF_ADDR as usize] = requested;
+
+        let mut cpu = CPU::new();
+        cpu.pc = PROGRAM_ADDR;
+        (cpu, ram)
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        // EI, NOP, NOP
+        let (mut cpu, mut ram) = load(&[0xfb, 0x00, 0x00], 0x01, 0x01);
+
+        cpu.step(&mut ram);
+        assert_eq!((cpu.pc, cpu.ime), (0x0101, 0));
+
+        cpu.step(&mut ram);
+        assert_eq!((cpu.pc, cpu.ime), (0x0102, 1));
+
+        cpu.step(&mut ram);
+        assert_eq!(cpu.pc, Interrupt::VBlank.vector());
+    }
+
+    #[test]
+    fn dispatch_pushes_pc_and_takes_20_cycles() {
+        let (mut cpu, mut ram) = load(&[0x00], 0x01, 0x01);
+        cpu.ime = 1;
+
+        cpu.step(&mut ram);
+
+        assert_eq!(cpu.pc, 0x0040);
+        assert_eq!(cpu.cycle_delay, 20);
+        assert_eq!(cpu.ime, 0);
+        assert_eq!(cpu.sp, 0xfffc);
+        assert_eq!(cpu.read_u16(0xfffc, &ram), PROGRAM_ADDR);
+        assert_eq!(ram.read(IF_ADDR), 0x00);
+    }
+
+    #[test]
+    fn the_lowest_pending_bit_is_serviced_first() {
+        let (mut cpu, mut ram) = load(&[0x00], 0x1e, 0x15);
+        cpu.ime = 1;
+
+        // VBlank is requested but not enabled, so the timer goes first
+        cpu.step(&mut ram);
+        assert_eq!(cpu.pc, Interrupt::Timer.vector());
+        assert_eq!(ram.read(IF_ADDR), 0x11);
+
+        cpu.ime = 1;
+        cpu.step(&mut ram);
+        assert_eq!(cpu.pc, Interrupt::Joypad.vector());
+        assert_eq!(ram.read(IF_ADDR), 0x01);
+    }
+
+    #[test]
+    fn halt_ends_on_a_pending_interrupt_without_ime() {
+        // HALT, INC A
+        let (mut cpu, mut ram) = load(&[0x76, 0x3c], 0x04, 0x00);
+
+        cpu.step(&mut ram);
+        cpu.step(&mut ram);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.pc, 0x0101);
+
+        ram.write(IF_ADDR, 0x04);
+        cpu.step(&mut ram);
+        assert!(!cpu.is_halted());
+        assert_eq!((cpu.pc, cpu.a), (0x0102, 1));
+        assert_eq!(ram.read(IF_ADDR), 0x04);
+    }
+
+    #[test]
+    fn halt_bug_reads_the_next_byte_twice() {
+        // HALT, INC A, NOP
+        let (mut cpu, mut ram) = load(&[0x76, 0x3c, 0x00], 0x01, 0x01);
+
+        cpu.step(&mut ram);
+        assert!(!cpu.is_halted());
+
+        cpu.step(&mut ram);
+        assert_eq!((cpu.pc, cpu.a), (0x0101, 1));
+
+        cpu.step(&mut ram);
+        assert_eq!((cpu.pc, cpu.a), (0x0102, 2));
+    }
+}
@@ -1,38 +1,105 @@
-
 use crate::cpu::CPU;
+use crate::mmu::Bus;
 use crate::cpu::registers::{ Reg8, Reg16, Condition };
 
+/// The CPU sits out 2050 M-cycles while the clock switches speed.
+const SPEED_SWITCH_CYCLES: u32 = 2050 * 4;
 
 impl CPU {
-    pub fn step(&mut self, data: &mut Vec<u8>) {
+    pub fn step<B: Bus>(&mut self, bus: &mut B) {
+        if self.speed_switch_cycles > 0 {
+            self.speed_switch_cycles -= 4;
+            self.cycle_delay = 4;
+            return;
+        }
+
         if self.stopped {
-            println!("stopped");
+            // STOP mode lasts until a button press pulls a joypad line low,
+            // a joypad flag left in IF doesn't count
+            if !bus.take_joypad_press() {
+                trace!("stopped");
+                self.cycle_delay = 4;
+                return;
+            }
+
+            self.stopped = false;
+        }
+
+        if self.locked {
+            self.cycle_delay = 4;
             return;
         }
 
-        let instruction = self.fetch(data);
-        println!("current instruction {:#02x}", instruction);
+        if self.service_interrupts(bus) {
+            return;
+        }
+
+        if self.halted {
+            self.cycle_delay = 4;
+            return;
+        }
+
+        let instruction = self.fetch(bus);
+        trace!("current instruction {:#02x}", instruction);
 
         match instruction {
             0 => {
                 // NOP
-                println!("NOP");
+                trace!("NOP");
                 self.cycle_delay = 4;
             },
 
             // JR PC+dd, JR Z, r8 (0x28), 0x38
-            0x20 => self.jr(Condition::NZ, data),
-            0x30 => self.jr(Condition::NC, data),
-            0x28 => self.jr(Condition::Z, data),
-            0x38 => self.jr(Condition::C, data),
-            0x18 => self.jr(Condition::Always, data),
+            0x20 => self.jr(Condition::NZ, bus),
+            0x30 => self.jr(Condition::NC, bus),
+            0x28 => self.jr(Condition::Z, bus),
+            0x38 => self.jr(Condition::C, bus),
+            0x18 => self.jr(Condition::Always, bus),
+
+            // JP a16
+            0xc2 => self.jp(Condition::NZ, bus),
+            0xd2 => self.jp(Condition::NC, bus),
+            0xca => self.jp(Condition::Z, bus),
+            0xda => self.jp(Condition::C, bus),
+            0xc3 => self.jp(Condition::Always, bus),
+
+            // JP HL
+            0xe9 => {
+                self.pc = self.get_hl();
+                self.cycle_delay = 4;
+            },
 
             // CALL a16
-            0xc4 => self.call(Condition::NZ, data),
-            0xd4 => self.call(Condition::NC, data),
-            0xcc => self.call(Condition::Z, data),
-            0xdc => self.call(Condition::C, data),
-            0xcd => self.call(Condition::Always, data),
+            0xc4 => self.call(Condition::NZ, bus),
+            0xd4 => self.call(Condition::NC, bus),
+            0xcc => self.call(Condition::Z, bus),
+            0xdc => self.call(Condition::C, bus),
+            0xcd => self.call(Condition::Always, bus),
+
+            // RET
+            0xc0 => self.ret(Condition::NZ, bus),
+            0xd0 => self.ret(Condition::NC, bus),
+            0xc8 => self.ret(Condition::Z, bus),
+            0xd8 => self.ret(Condition::C, bus),
+            0xc9 => self.ret(Condition::Always, bus),
+
+            // RETI enables interrupts without the EI delay
+            0xd9 => {
+                self.ret(Condition::Always, bus);
+                self.ime = 1;
+            },
+
+            // RST n
+            0xc7 | 0xcf | 0xd7 | 0xdf |
+            0xe7 | 0xef | 0xf7 | 0xff => {
+                let target = (instruction & 0x38) as u16;
+                trace!("RST {:#04x}", target);
+
+                self.sp = self.sp.wrapping_sub(2);
+                self.store_u16(self.sp, self.pc, bus);
+                self.pc = target;
+                self.cycle_delay = 16;
+            },
 
             // Register to Register Loads
             0x40 => self.ld_u8(Reg8::B, Reg8::B),
@@ -91,107 +158,202 @@ impl CPU {
             0x7d => self.ld_u8(Reg8::A, Reg8::L),
             0x7f => self.ld_u8(Reg8::A, Reg8::A),
 
+            // LD r8, (HL)
+            0x46 => self.ld_r8_hl(Reg8::B, bus),
+            0x4e => self.ld_r8_hl(Reg8::C, bus),
+            0x56 => self.ld_r8_hl(Reg8::D, bus),
+            0x5e => self.ld_r8_hl(Reg8::E, bus),
+            0x66 => self.ld_r8_hl(Reg8::H, bus),
+            0x6e => self.ld_r8_hl(Reg8::L, bus),
+            0x7e => self.ld_r8_hl(Reg8::A, bus),
+
+            // LD (HL), r8
+            0x70 => self.ld_hl_r8(Reg8::B, bus),
+            0x71 => self.ld_hl_r8(Reg8::C, bus),
+            0x72 => self.ld_hl_r8(Reg8::D, bus),
+            0x73 => self.ld_hl_r8(Reg8::E, bus),
+            0x74 => self.ld_hl_r8(Reg8::H, bus),
+            0x75 => self.ld_hl_r8(Reg8::L, bus),
+            0x77 => self.ld_hl_r8(Reg8::A, bus),
+
+            // HALT
+            0x76 => {
+                trace!("HALT");
+                self.halt(bus);
+                self.cycle_delay = 4;
+            },
+
             // Load Immediate 8bit
-            0x06 => self.ld_imm_u8(Reg8::B, data),
-            0x16 => self.ld_imm_u8(Reg8::D, data),
-            0x26 => self.ld_imm_u8(Reg8::H, data),
-            0x0e => self.ld_imm_u8(Reg8::C, data),
-            0x1e => self.ld_imm_u8(Reg8::E, data),
-            0x2e => self.ld_imm_u8(Reg8::L, data),
-            0x3e => self.ld_imm_u8(Reg8::A, data),
+            0x06 => self.ld_imm_u8(Reg8::B, bus),
+            0x16 => self.ld_imm_u8(Reg8::D, bus),
+            0x26 => self.ld_imm_u8(Reg8::H, bus),
+            0x0e => self.ld_imm_u8(Reg8::C, bus),
+            0x1e => self.ld_imm_u8(Reg8::E, bus),
+            0x2e => self.ld_imm_u8(Reg8::L, bus),
+            0x3e => self.ld_imm_u8(Reg8::A, bus),
+
+            // LD (HL), d8
+            0x36 => {
+                let imm = self.fetch(bus);
+                self.store(self.get_hl(), imm, bus);
+                self.cycle_delay = 12;
+            },
 
             // Load Immediate 16bit
-            0x01 => self.ld_imm_u16(Reg16::BC, data),
-            0x11 => self.ld_imm_u16(Reg16::DE, data),
-            0x21 => self.ld_imm_u16(Reg16::HL, data),
-            0x31 => self.ld_imm_u16(Reg16::SP, data),
-
+            0x01 => self.ld_imm_u16(Reg16::BC, bus),
+            0x11 => self.ld_imm_u16(Reg16::DE, bus),
+            0x21 => self.ld_imm_u16(Reg16::HL, bus),
+            0x31 => self.ld_imm_u16(Reg16::SP, bus),
+
+            // LD (a16), SP
+            0x08 => {
+                let addr = self.fetch_u16(bus);
+                self.store_u16(addr, self.sp, bus);
+                self.cycle_delay = 20;
+            },
 
-            // LD (HL), A
-            0x77 => {
-                self.store(self.get_hl(), self.a, data);
+            // LD SP, HL
+            0xf9 => {
+                self.sp = self.get_hl();
                 self.cycle_delay = 8;
             },
 
-            // Jump 16bit address
-            0xc3 => {
-                let addr = self.fetch_u16(data);
-                println!("JMP {:#08x}", addr);
+            // LD HL, SP+e8
+            0xf8 => {
+                let offset = self.fetch(bus);
+                let value = self.alu_add_sp(self.sp, offset);
+                self.set_hl(value);
+                self.cycle_delay = 12;
+            },
 
-                self.pc = addr;
+            // ADD SP, e8
+            0xe8 => {
+                let offset = self.fetch(bus);
+                self.sp = self.alu_add_sp(self.sp, offset);
                 self.cycle_delay = 16;
             },
 
-            // RET
-            0xc9 => {
-                self.pc = self.read_u16(self.sp, data);
-                self.sp += 2;
-                self.cycle_delay = 16;
+            // LD (BC), A / LD (DE), A
+            0x02 => {
+                self.store(self.get_bc(), self.a, bus);
+                self.cycle_delay = 8;
+            },
+            0x12 => {
+                self.store(self.get_de(), self.a, bus);
+                self.cycle_delay = 8;
+            },
+
+            // LD A, (BC) / LD A, (DE)
+            0x0a => {
+                self.a = self.read(self.get_bc(), bus);
+                self.cycle_delay = 8;
+            },
+            0x1a => {
+                self.a = self.read(self.get_de(), bus);
+                self.cycle_delay = 8;
+            },
+
+            // LDI (HL+), A
+            0x22 => {
+                trace!("LDI (HL+), A");
+                let hl = self.get_hl();
+                self.store(hl, self.a, bus);
+                self.set_hl(hl.wrapping_add(1));
+                self.cycle_delay = 8;
+            },
+
+            // LDD (HL-), A
+            0x32 => {
+                trace!("LDD (HL-), A");
+                let hl = self.get_hl();
+                self.store(hl, self.a, bus);
+                self.set_hl(hl.wrapping_sub(1));
+                self.cycle_delay = 8;
             },
-            
+
             // Disable Interrupts
             0xf3 => {
                 self.ime = 0;
+                self.ei_delay = 0;
                 self.cycle_delay = 4;
             },
-            
+
             // Enable Interrupts
             0xfb => {
-                self.ime = 1;
+                self.enable_interrupts_delayed();
                 self.cycle_delay = 4;
             },
-            
-            
 
             // Write to IO-Port n FF00+n
             0xe0 => {
-                let addr: u16 = 0xFF00_u16 + self.fetch(data) as u16;
-                self.store(addr, self.a, data);
+                let addr: u16 = 0xFF00_u16 + self.fetch(bus) as u16;
+                self.store(addr, self.a, bus);
                 self.cycle_delay = 12;
             },
 
             // Read from IO-Port n FF00+n
             0xf0 => {
-                let addr: u16 = 0xFF00_u16 + self.fetch(data) as u16;
-                self.a = self.read(addr, data);
+                let addr: u16 = 0xFF00_u16 + self.fetch(bus) as u16;
+                self.a = self.read(addr, bus);
                 self.cycle_delay = 12;
             },
 
+            // Write to IO-Port C FF00+C
+            0xe2 => {
+                let addr: u16 = 0xFF00_u16 + self.c as u16;
+                self.store(addr, self.a, bus);
+                self.cycle_delay = 8;
+            },
+
+            // Read from IO-Port C FF00+C
+            0xf2 => {
+                let addr: u16 = 0xFF00_u16 + self.c as u16;
+                self.a = self.read(addr, bus);
+                self.cycle_delay = 8;
+            },
+
             // Store A at Address
             0xea => {
-                let addr = self.fetch_u16(data);
-                self.store(addr, self.a, data);
-                self.cycle_delay = 12;
+                let addr = self.fetch_u16(bus);
+                self.store(addr, self.a, bus);
+                self.cycle_delay = 16;
             },
-            
-            
+
             // STOP
             0x10 => {
-                self.stopped = true;
-                self.cycle_delay = 12;
-                self.fetch(data);
+                self.cycle_delay = 4;
+                self.fetch(bus);
+
+                // without a speed switch the CPU sleeps until the next
+                // button press, one from before doesn't count
+                if bus.speed_switch() {
+                    self.speed_switch_cycles = SPEED_SWITCH_CYCLES;
+                } else {
+                    bus.take_joypad_press();
+                    self.stopped = true;
+                }
             },
 
             // PUSH rr
-            0xc5 => self.push(Reg16::BC, data),
-            0xd5 => self.push(Reg16::DE, data),
-            0xe5 => self.push(Reg16::HL, data),
-            0xf5 => self.push(Reg16::AF, data),
+            0xc5 => self.push(Reg16::BC, bus),
+            0xd5 => self.push(Reg16::DE, bus),
+            0xe5 => self.push(Reg16::HL, bus),
+            0xf5 => self.push(Reg16::AF, bus),
 
             // POP rr
-            0xc1 => self.pop(Reg16::BC, data),
-            0xd1 => self.pop(Reg16::DE, data),
-            0xe1 => self.pop(Reg16::HL, data),
-            0xf1 => self.pop(Reg16::AF, data),
+            0xc1 => self.pop(Reg16::BC, bus),
+            0xd1 => self.pop(Reg16::DE, bus),
+            0xe1 => self.pop(Reg16::HL, bus),
+            0xf1 => self.pop(Reg16::AF, bus),
 
             // INC rr
             0x03 | 0x13 | 0x23 | 0x33 => {
-                println!("INC rr");
+                trace!("INC rr");
                 match instruction {
                     0x03 => self.set_bc(self.alu_inc_u16(self.get_bc())),
                     0x13 => self.set_de(self.alu_inc_u16(self.get_de())),
                     0x23 => self.set_hl(self.alu_inc_u16(self.get_hl())),
-                    0x33 => self.sp += 1,
+                    0x33 => self.sp = self.alu_inc_u16(self.sp),
                     _ => unreachable!()
                 };
                 self.cycle_delay = 8;
@@ -199,32 +361,38 @@ impl CPU {
 
             // DEC rr
             0x0b | 0x1b | 0x2b | 0x3b => {
-                println!("DEC rr");
+                trace!("DEC rr");
                 match instruction {
                     0x0b => self.set_bc(self.alu_dec_u16(self.get_bc())),
                     0x1b => self.set_de(self.alu_dec_u16(self.get_de())),
                     0x2b => self.set_hl(self.alu_dec_u16(self.get_hl())),
-                    0x3b => self.sp -= 1,
+                    0x3b => self.sp = self.alu_dec_u16(self.sp),
                     _ => unreachable!()
                 };
                 self.cycle_delay = 8;
             },
 
+            // ADD HL, rr
+            0x09 => self.add_hl(Reg16::BC),
+            0x19 => self.add_hl(Reg16::DE),
+            0x29 => self.add_hl(Reg16::HL),
+            0x39 => self.add_hl(Reg16::SP),
+
             // LDI A, (HL+)
             0x2a => {
-                println!("LDI A, (HL+)");
+                trace!("LDI A, (HL+)");
                 let hl = self.get_hl();
-                self.a = self.read(hl, data);
-                self.set_hl(hl+1);
+                self.a = self.read(hl, bus);
+                self.set_hl(hl.wrapping_add(1));
                 self.cycle_delay = 8;
             },
 
-            // LDI A, (HL-)
+            // LDD A, (HL-)
             0x3a => {
-                println!("LDI A, (HL-)");
+                trace!("LDD A, (HL-)");
                 let hl = self.get_hl();
-                self.a = self.read(hl, data);
-                self.set_hl(hl-1);
+                self.a = self.read(hl, bus);
+                self.set_hl(hl.wrapping_sub(1));
                 self.cycle_delay = 8;
             },
 
@@ -237,33 +405,28 @@ impl CPU {
             0x2c => self.inc_r8(Reg8::L),
             0x3c => self.inc_r8(Reg8::A),
 
+            // INC (HL)
             0x34 => {
-                self.unset_flag(CPU::FLAG_ZERO | CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY);
-
-                self.cycle_delay = 4;
-                
                 let addr = self.get_hl();
-                let value = self.alu_inc(self.read(addr, data)); 
-                self.store(addr, value, data);
-                self.cycle_delay = 12; 
+                let value = self.alu_inc(self.read(addr, bus));
+                self.store(addr, value, bus);
+                self.cycle_delay = 12;
             },
 
             // DEC r8
             0x05 | 0x15 | 0x25 | 0x35 |
             0x0d | 0x1d | 0x2d | 0x3d => {
-                self.unset_flag(CPU::FLAG_ZERO | CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY | CPU::FLAG_CARRY);
-
                 self.cycle_delay = 4;
 
                 match instruction {
                     0x05 => self.b = self.alu_dec(self.b),
                     0x15 => self.d = self.alu_dec(self.d),
                     0x25 => self.h = self.alu_dec(self.h),
-                    0x35 => { 
+                    0x35 => {
                         let addr = self.get_hl();
-                        let value = self.alu_dec(self.read(addr, data)); 
-                        self.store(addr, value, data);
-                        self.cycle_delay = 12; 
+                        let value = self.alu_dec(self.read(addr, bus));
+                        self.store(addr, value, bus);
+                        self.cycle_delay = 12;
                     },
                     0x0d => self.c = self.alu_dec(self.c),
                     0x1d => self.e = self.alu_dec(self.e),
@@ -273,110 +436,125 @@ impl CPU {
                 };
             },
 
-            // OR
-            0xb0..=0xb7 => {
-                self.unset_flag(CPU::FLAG_ZERO | CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY | CPU::FLAG_CARRY);
-
+            // ADD/ADC/SUB/SBC/AND/XOR/OR/CP A, r8
+            0x80..=0xbf => {
                 self.cycle_delay = 4;
 
-                match instruction {
-                    0xb0 => self.a |= self.b,
-                    0xb1 => self.a |= self.c,
-                    0xb2 => self.a |= self.d,
-                    0xb3 => self.a |= self.e,
-                    0xb4 => self.a |= self.h,
-                    0xb5 => self.a |= self.l,
-                    0xb7 => self.a |= self.a,
-                    0xb6 => { self.a |= self.read(self.get_hl(), data); self.cycle_delay = 8 },
+                let value = match instruction & 0x07 {
+                    0 => self.b,
+                    1 => self.c,
+                    2 => self.d,
+                    3 => self.e,
+                    4 => self.h,
+                    5 => self.l,
+                    6 => {
+                        self.cycle_delay = 8;
+                        self.read(self.get_hl(), bus)
+                    },
+                    7 => self.a,
                     _ => unreachable!()
                 };
 
-                if self.a == 0 {
-                    self.set_flag(CPU::FLAG_ZERO);
-                }
+                self.alu_op((instruction >> 3) & 0x07, value);
             },
 
-            // AND
-            0xa0..=0xa7 | 0xe6 => {
-                self.unset_flag(CPU::FLAG_ZERO | CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY | CPU::FLAG_CARRY);
-
-                self.cycle_delay = 4;
+            // ADD/ADC/SUB/SBC/AND/XOR/OR/CP A, d8
+            0xc6 | 0xce | 0xd6 | 0xde |
+            0xe6 | 0xee | 0xf6 | 0xfe => {
+                let imm = self.fetch(bus);
+                self.alu_op((instruction >> 3) & 0x07, imm);
+                self.cycle_delay = 8;
+            },
 
-                match instruction {
-                    0xa0 => self.a &= self.b,
-                    0xa1 => self.a &= self.c,
-                    0xa2 => self.a &= self.d,
-                    0xa3 => self.a &= self.e,
-                    0xa4 => self.a &= self.h,
-                    0xa5 => self.a &= self.l,
-                    0xa7 => self.a &= self.a,
-                    0xa6 => { 
-                        self.a &= self.read(self.get_hl(), data); 
-                        self.cycle_delay = 8; 
-                    },
-                    0xe6 => {
-                        self.a &= self.fetch(data); 
-                        self.cycle_delay = 8; 
-                    },
+            // RLCA / RRCA / RLA / RRA always clear the zero flag
+            0x07 | 0x0f | 0x17 | 0x1f => {
+                self.a = match instruction {
+                    0x07 => self.alu_rlc(self.a),
+                    0x0f => self.alu_rrc(self.a),
+                    0x17 => self.alu_rl(self.a),
+                    0x1f => self.alu_rr(self.a),
                     _ => unreachable!()
                 };
+                self.unset_flag(CPU::FLAG_ZERO);
+                self.cycle_delay = 4;
+            },
 
-                self.set_flag(CPU::FLAG_HALF_CARRY);
+            // DAA
+            0x27 => {
+                self.a = self.alu_daa(self.a);
+                self.cycle_delay = 4;
+            },
 
-                if self.a == 0 {
-                    self.set_flag(CPU::FLAG_ZERO);
-                }
+            // CPL
+            0x2f => {
+                self.a = !self.a;
+                self.set_flag(CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY);
+                self.cycle_delay = 4;
             },
 
-            // CP A, r8
-            0xfe => {
-                self.f = 0;
-                
-                let imm = self.fetch(data);
-                self.a = self.alu_sub(self.a, imm);
-                self.set_flag(CPU::FLAG_SUBTRACT);
+            // SCF
+            0x37 => {
+                self.unset_flag(CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY);
+                self.set_flag(CPU::FLAG_CARRY);
+                self.cycle_delay = 4;
+            },
 
-                if self.a == 0 {
-                    self.set_flag(CPU::FLAG_ZERO);
-                }
-                
-                self.cycle_delay = 8;
+            // CCF
+            0x3f => {
+                let carry = self.read_flag(CPU::FLAG_CARRY);
+                self.unset_flag(CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY);
+                self.write_flag(CPU::FLAG_CARRY, !carry);
+                self.cycle_delay = 4;
             },
 
             // LD A, (a16) 0xfa
             0xfa => {
-                let imm = self.fetch_u16(data);
-                self.a = self.read(imm, data);
+                let imm = self.fetch_u16(bus);
+                self.a = self.read(imm, bus);
                 self.cycle_delay = 16;
             },
 
-            _ => {
-                panic!("instruction {:#02x} not yet implemented", instruction);
-            }
+            // PREFIX CB
+            0xcb => self.step_prefixed(bus),
+
+            // Illegal opcodes hang the CPU until it is reset
+            0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 |
+            0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+                trace!("illegal instruction {:#02x}, locking up", instruction);
+                self.locked = true;
+                self.cycle_delay = 4;
+            },
         }
-    }
 
-    fn ld_imm_u8(&mut self, dst: Reg8, data: &Vec<u8>) {
-        println!("LD {:?}, d8", dst);
-        let imm = self.fetch(data);
+        self.tick_ime_delay();
+    }
 
-        match dst {
-            Reg8::A => self.a = imm,
-            Reg8::B => self.b = imm,
-            Reg8::C => self.c = imm,
-            Reg8::D => self.d = imm,
-            Reg8::E => self.e = imm,
-            Reg8::L => self.l = imm,
-            Reg8::H => self.h = imm,
-            Reg8::F => unreachable!(),
+    fn alu_op(&mut self, op: u8, value: u8) {
+        match op {
+            0 => self.a = self.alu_add(self.a, value),
+            1 => self.a = self.alu_adc(self.a, value, self.read_flag(CPU::FLAG_CARRY)),
+            2 => self.a = self.alu_sub(self.a, value),
+            3 => self.a = self.alu_sbc(self.a, value, self.read_flag(CPU::FLAG_CARRY)),
+            4 => self.a = self.alu_and(self.a, value),
+            5 => self.a = self.alu_xor(self.a, value),
+            6 => self.a = self.alu_or(self.a, value),
+            7 => self.alu_cp(self.a, value),
+            _ => unreachable!()
         }
+    }
+
+    fn ld_imm_u8<B: Bus>(&mut self, dst: Reg8, bus: &B) {
+        trace!("LD {:?}, d8", dst);
+        let imm = self.fetch(bus);
+
+        self.set_r8(dst, imm);
         self.cycle_delay = 8;
     }
 
-    fn ld_imm_u16(&mut self, dst: Reg16, data: &Vec<u8>) {
-        println!("LD {:?}, d16", dst);
-        let imm = self.fetch_u16(data);
-        
+    fn ld_imm_u16<B: Bus>(&mut self, dst: Reg16, bus: &B) {
+        trace!("LD {:?}, d16", dst);
+        let imm = self.fetch_u16(bus);
+
         match dst {
             Reg16::BC => self.set_bc(imm),
             Reg16::DE => self.set_de(imm),
@@ -385,10 +563,26 @@ impl CPU {
             Reg16::AF => unreachable!(),
         }
 
+        self.cycle_delay = 12;
+    }
+
+    fn ld_r8_hl<B: Bus>(&mut self, dst: Reg8, bus: &B) {
+        trace!("LD {:?}, (HL)", dst);
+        let value = self.read(self.get_hl(), bus);
+
+        self.set_r8(dst, value);
         self.cycle_delay = 8;
     }
 
-    fn get_r8(&self, register: Reg8) -> u8 {
+    fn ld_hl_r8<B: Bus>(&mut self, src: Reg8, bus: &mut B) {
+        trace!("LD (HL), {:?}", src);
+        let value = self.get_r8(src);
+
+        self.store(self.get_hl(), value, bus);
+        self.cycle_delay = 8;
+    }
+
+    pub(super) fn get_r8(&self, register: Reg8) -> u8 {
         match register {
             Reg8::A => self.a,
             Reg8::B => self.b,
@@ -401,7 +595,7 @@ impl CPU {
         }
     }
 
-    fn set_r8(&mut self, register: Reg8, value: u8) {
+    pub(super) fn set_r8(&mut self, register: Reg8, value: u8) {
         match register {
             Reg8::A => self.a = value,
             Reg8::B => self.b = value,
@@ -410,7 +604,7 @@ impl CPU {
             Reg8::E => self.e = value,
             Reg8::L => self.l = value,
             Reg8::H => self.h = value,
-            Reg8::F => self.f = value,
+            Reg8::F => self.f = value & 0xf0,
         }
     }
 
@@ -435,8 +629,8 @@ impl CPU {
     }
 
     fn ld_u8(&mut self, dst: Reg8, src: Reg8) {
-        println!("LD {:?}, {:?}", dst, src);
-        
+        trace!("LD {:?}, {:?}", dst, src);
+
         let value = self.get_r8(src);
         self.set_r8(dst, value);
 
@@ -453,13 +647,13 @@ impl CPU {
         }
     }
 
-    fn call(&mut self, condition: Condition, data: &mut Vec<u8>){
-        let next_addr = self.fetch_u16(data);
-        println!("CALL {:?}, {:#04x}", condition, next_addr);
-        
+    fn call<B: Bus>(&mut self, condition: Condition, bus: &mut B){
+        let next_addr = self.fetch_u16(bus);
+        trace!("CALL {:?}, {:#04x}", condition, next_addr);
+
         if self.check_condition(condition) {
-            self.sp -= 2;
-            self.store_u16(self.sp, self.pc, data);
+            self.sp = self.sp.wrapping_sub(2);
+            self.store_u16(self.sp, self.pc, bus);
             self.pc = next_addr;
             self.cycle_delay = 24;
         } else {
@@ -467,45 +661,81 @@ impl CPU {
         }
     }
 
-    fn jr(&mut self, condition: Condition, data: &mut Vec<u8>){
-        let bytes = self.fetch(data).to_le_bytes();
-        let offset:i32 = i8::from_le_bytes(bytes) as i32;
-        println!("JR {:?}", condition);
+    fn ret<B: Bus>(&mut self, condition: Condition, bus: &B){
+        trace!("RET {:?}", condition);
+
+        // the unconditional form skips the condition check and is one cycle shorter
+        let (taken, not_taken) = match condition {
+            Condition::Always => (16, 0),
+            _ => (20, 8)
+        };
+
+        if self.check_condition(condition) {
+            self.pc = self.read_u16(self.sp, bus);
+            self.sp = self.sp.wrapping_add(2);
+            self.cycle_delay = taken;
+        } else {
+            self.cycle_delay = not_taken;
+        }
+    }
+
+    fn jp<B: Bus>(&mut self, condition: Condition, bus: &B){
+        let addr = self.fetch_u16(bus);
+        trace!("JP {:?}, {:#04x}", condition, addr);
+
+        if self.check_condition(condition) {
+            self.pc = addr;
+            self.cycle_delay = 16;
+        } else {
+            self.cycle_delay = 12;
+        }
+    }
+
+    fn jr<B: Bus>(&mut self, condition: Condition, bus: &B){
+        let offset = self.fetch(bus) as i8;
+        trace!("JR {:?}", condition);
 
         if self.check_condition(condition) {
             self.cycle_delay = 12;
-            self.pc = (offset + (self.pc as i32)) as u16;
+            self.pc = self.pc.wrapping_add(offset as u16);
         } else {
             self.cycle_delay = 8;
         }
     }
 
-    fn push(&mut self, register: Reg16, data: &mut Vec<u8>){
-        println!("PUSH {:?}", register);
-        self.sp -= 2;
+    fn push<B: Bus>(&mut self, register: Reg16, bus: &mut B){
+        trace!("PUSH {:?}", register);
+        self.sp = self.sp.wrapping_sub(2);
         let value = self.get_r16(register);
 
-        self.store_u16(self.sp, value, data);
+        self.store_u16(self.sp, value, bus);
         self.cycle_delay = 16;
     }
 
-    fn pop(&mut self, register: Reg16, data: &mut Vec<u8>){
-        println!("POP {:?}", register);
-        let value = self.read_u16(self.sp, data);
+    fn pop<B: Bus>(&mut self, register: Reg16, bus: &B){
+        trace!("POP {:?}", register);
+        let value = self.read_u16(self.sp, bus);
 
         self.set_r16(register, value);
 
-        self.sp += 2;
+        self.sp = self.sp.wrapping_add(2);
         self.cycle_delay = 12;
     }
 
-    fn inc_r8(&mut self, register: Reg8){
-        self.unset_flag(CPU::FLAG_ZERO | CPU::FLAG_SUBTRACT | CPU::FLAG_HALF_CARRY);
+    fn add_hl(&mut self, register: Reg16){
+        trace!("ADD HL, {:?}", register);
+        let value = self.get_r16(register);
+        let result = self.alu_add_u16(self.get_hl(), value);
 
+        self.set_hl(result);
+        self.cycle_delay = 8;
+    }
+
+    fn inc_r8(&mut self, register: Reg8){
         self.cycle_delay = 4;
         let current_value = self.get_r8(register);
         let new_value = self.alu_inc(current_value);
 
         self.set_r8(register, new_value);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,76 @@
+use crate::cpu::CPU;
+use crate::mmu::Bus;
+use crate::cpu::registers::{ Reg8, Operand8 };
+
+
+impl CPU {
+    /// Executes the instruction following a 0xCB prefix. The low three bits
+    /// select the operand, the upper five the operation.
+    pub(super) fn step_prefixed<B: Bus>(&mut self, bus: &mut B) {
+        let instruction = self.fetch(bus);
+        trace!("current prefixed instruction {:#02x}", instruction);
+
+        let operand = match instruction & 0x07 {
+            0 => Operand8::Reg(Reg8::B),
+            1 => Operand8::Reg(Reg8::C),
+            2 => Operand8::Reg(Reg8::D),
+            3 => Operand8::Reg(Reg8::E),
+            4 => Operand8::Reg(Reg8::H),
+            5 => Operand8::Reg(Reg8::L),
+            6 => Operand8::IndirectHL,
+            7 => Operand8::Reg(Reg8::A),
+            _ => unreachable!()
+        };
+
+        let bit = (instruction >> 3) & 0x07;
+        let value = self.read_operand(operand, bus);
+
+        match instruction {
+            // RLC/RRC/RL/RR/SLA/SRA/SWAP/SRL
+            0x00..=0x3f => {
+                let result = match instruction >> 3 {
+                    0 => self.alu_rlc(value),
+                    1 => self.alu_rrc(value),
+                    2 => self.alu_rl(value),
+                    3 => self.alu_rr(value),
+                    4 => self.alu_sla(value),
+                    5 => self.alu_sra(value),
+                    6 => self.alu_swap(value),
+                    7 => self.alu_srl(value),
+                    _ => unreachable!()
+                };
+                self.write_operand(operand, result, bus);
+            },
+
+            // BIT n, r8
+            0x40..=0x7f => self.alu_bit(bit, value),
+
+            // RES n, r8
+            0x80..=0xbf => self.write_operand(operand, value & !(1 << bit), bus),
+
+            // SET n, r8
+            0xc0..=0xff => self.write_operand(operand, value | (1 << bit), bus),
+        }
+
+        // the prefix fetch is included, BIT only reads (HL) and skips the write back
+        self.cycle_delay = match (operand, instruction) {
+            (Operand8::Reg(_), _) => 8,
+            (Operand8::IndirectHL, 0x40..=0x7f) => 12,
+            (Operand8::IndirectHL, _) => 16,
+        };
+    }
+
+    fn read_operand<B: Bus>(&self, operand: Operand8, bus: &B) -> u8 {
+        match operand {
+            Operand8::Reg(register) => self.get_r8(register),
+            Operand8::IndirectHL => self.read(self.get_hl(), bus),
+        }
+    }
+
+    fn write_operand<B: Bus>(&mut self, operand: Operand8, value: u8, bus: &mut B) {
+        match operand {
+            Operand8::Reg(register) => self.set_r8(register, value),
+            Operand8::IndirectHL => self.store(self.get_hl(), value, bus),
+        }
+    }
+}
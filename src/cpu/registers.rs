@@ -14,3 +14,10 @@ pub enum Condition {
     Z, NZ, C, NC, Always
 }
 
+
+/// An 8 bit operand as encoded in the low three bits of an opcode, either a
+/// register or the byte in memory pointed to by HL.
+#[derive(Debug, Copy, Clone)]
+pub enum Operand8 {
+    Reg(Reg8), IndirectHL
+}
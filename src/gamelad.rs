@@ -1,30 +1,158 @@
 
 
+use crate::apu::resampler::Resampler;
+use crate::boot::{ BootRom, BootRomError };
+use crate::cartridge::{ Cartridge, CartridgeError };
+use crate::cartridge::camera::ImageSource;
+use crate::cartridge::mbc::CLOCK_SPEED;
+use crate::cartridge::mbc7::TiltSource;
 use crate::cpu::CPU;
-use std::fs;
+use crate::joypad::Button;
+use crate::mmu::{ Bus, Mmu };
+use crate::model::Model;
+use crate::ppu::{ RendererKind, CYCLES_PER_FRAME };
+use crate::recorder::{ Recorder, RECORDING_SAMPLE_RATE };
+use crate::save::{ FileStorage, SaveStorage };
+
+use std::io;
+use std::path::Path;
+
+/// How often dirty save RAM is written back while running, in cycles.
+const SAVE_INTERVAL: u32 = CLOCK_SPEED;
 
 pub struct Gamelad {
     cpu: CPU,
-    rom: Vec<u8>
+    mmu: Mmu,
+    model: Model,
+    boot_rom: Option<BootRom>,
+    rumble: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
+    storage: Option<Box<dyn SaveStorage>>,
+    save_cycles: u32,
+    recorder: Option<Recorder>,
+    /// Why the recording stopped early, until it is taken.
+    recording_error: Option<io::Error>
 }
 
 impl Gamelad {
-    pub fn new(filename: &str) -> Gamelad {
+    /// Loads the ROM at `filename`. Without a `model` the one the cartridge
+    /// header asks for is emulated, `renderer` picks how the PPU draws.
+    pub fn new(filename: &str, model: Option<Model>, renderer: RendererKind) -> Result<Gamelad, CartridgeError> {
         println!("loading {}..", filename);
 
-        let memory = fs::read(filename)
-            .expect("Could not load binary");
-    
-        println!("Binary Size:\n{}", memory.len());
+        let cartridge = Cartridge::load(filename)?;
+
+        println!("Cartridge:\n{}", cartridge);
+
+        if let Err(err) = cartridge.validate() {
+            println!("warning: {}", err);
+        }
 
-        let mut cpu = CPU::new();
+        let storage: Option<Box<dyn SaveStorage>> = if cartridge.has_battery() {
+            Some(Box::new(FileStorage::for_rom(filename)))
+        } else {
+            None
+        };
+
+        Ok(Gamelad::from_cartridge(cartridge, model, renderer, storage))
+    }
+
+    /// Builds an emulator around an already loaded cartridge. Battery backed
+    /// RAM is restored from `storage` and written back to it. Nothing runs
+    /// until `reset`, which also picks between the boot ROM and skipping it.
+    pub fn from_cartridge(
+        mut cartridge: Cartridge,
+        model: Option<Model>,
+        renderer: RendererKind,
+        mut storage: Option<Box<dyn SaveStorage>>
+    ) -> Gamelad {
+        if let Some(storage) = storage.as_mut() {
+            match storage.load() {
+                Ok(Some(data)) => cartridge.load_save_data(&data),
+                Ok(None) => {},
+                Err(err) => println!("warning: could not load save: {}", err)
+            }
+        }
 
-        cpu.pc = 0x0100;
+        let model = model.unwrap_or_else(|| Model::for_cartridge(&cartridge));
 
         Gamelad {
-            cpu: cpu,
-            rom: memory,
+            cpu: CPU::new(),
+            mmu: Mmu::new(cartridge, model, renderer),
+            model,
+            boot_rom: None,
+            rumble: false,
+            rumble_callback: None,
+            storage,
+            save_cycles: 0,
+            recorder: None,
+            recording_error: None
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn mmu(&self) -> &Mmu {
+        &self.mmu
+    }
+
+    pub fn mmu_mut(&mut self) -> &mut Mmu {
+        &mut self.mmu
+    }
+
+    /// True once the CPU has hung on an illegal instruction, see
+    /// `CPU::is_locked`.
+    pub fn is_locked(&self) -> bool {
+        self.cpu.is_locked()
+    }
+
+    /// True while the CPU sits in STOP, see `set_button`.
+    pub fn is_stopped(&self) -> bool {
+        self.cpu.is_stopped()
+    }
+
+    /// Presses or releases `button`. A STOP that didn't switch speed only
+    /// ends with a button press on a selected line, so a headless run that
+    /// never presses anything hangs on one.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.mmu.joypad_mut().set_button(button, pressed);
+    }
+
+    /// Runs `boot_rom` on the next reset instead of skipping straight to
+    /// the cartridge with the state the boot ROM would have left behind.
+    pub fn set_boot_rom(&mut self, boot_rom: BootRom) -> Result<(), BootRomError> {
+        if boot_rom.is_cgb() != self.model.is_cgb() {
+            return Err(BootRomError::WrongModel(self.model));
         }
+
+        self.boot_rom = Some(boot_rom);
+        Ok(())
+    }
+
+    /// Writes the cartridge RAM to the save storage, if there is one.
+    pub fn save(&mut self) -> io::Result<()> {
+        match self.storage.as_mut() {
+            Some(storage) => storage.save(&self.mmu.cartridge().save_data()),
+            None => Ok(())
+        }
+    }
+
+    /// Called with the new motor state whenever a rumble cartridge turns it
+    /// on or off, so frontends can forward it to a controller.
+    pub fn set_rumble_callback<F: FnMut(bool) + 'static>(&mut self, callback: F) {
+        self.rumble_callback = Some(Box::new(callback));
+    }
+
+    /// Feeds the accelerometer on MBC7 cartridges.
+    pub fn set_tilt_source(&mut self, source: TiltSource) {
+        self.mmu.cartridge_mut().set_tilt_source(source);
+    }
+
+    /// Feeds the image sensor on Game Boy Camera cartridges.
+    pub fn set_image_source(&mut self, source: ImageSource) {
+        self.mmu.cartridge_mut().set_image_source(source);
     }
 
     pub fn run(&mut self){
@@ -32,66 +160,388 @@ impl Gamelad {
 
         let mut cycle = 0;
 
-        while !self.cpu.is_stopped() {
+        while !self.cpu.is_locked() {
             cycle += 1;
-            println!("cycle #{}", cycle);
-            println!("Initial State {}", self.cpu);
-            self.cpu.step(&mut self.rom);
-            println!();
+            trace!("cycle #{}", cycle);
+            trace!("Initial State {}", self.cpu);
+            self.step();
+            trace!();
         }
     }
 
+    /// Runs until the PPU finishes a frame, or for as long as a frame would
+    /// take while the LCD is off.
+    pub fn run_frame(&mut self) {
+        let mut cycles = 0;
+
+        while !self.mmu.ppu_mut().take_frame() {
+            if !self.mmu.ppu().lcd_enabled() && cycles >= CYCLES_PER_FRAME {
+                break;
+            }
+
+            let delay = self.step();
+
+            // a frame is measured at normal speed
+            cycles += if self.mmu.double_speed() { delay / 2 } else { delay };
+        }
+
+        if let Err(err) = self.write_recording() {
+            // the files are finished as far as they go, the first error is
+            // the one worth reporting
+            let _ = self.stop_recording();
+            self.recording_error = Some(err);
+        }
+    }
+
+    /// The last frame, SCREEN_WIDTH x SCREEN_HEIGHT 15 bit colors with red
+    /// in the low bits.
+    pub fn framebuffer(&self) -> &[u16] {
+        self.mmu.ppu().framebuffer()
+    }
+
+    /// The APU's resampled output, for picking the sample rate and filter.
+    pub fn audio_mut(&mut self) -> &mut Resampler {
+        self.mmu.apu_mut().output_mut()
+    }
+
+    /// Pulls up to `out.len() / 2` stereo samples into `out`, interleaved
+    /// left and right, returning how many there were.
+    pub fn read_audio_f32(&mut self, out: &mut [f32]) -> usize {
+        self.audio_mut().read_f32(out)
+    }
+
+    /// Like `read_audio_f32`, with 16 bit samples.
+    pub fn read_audio_i16(&mut self, out: &mut [i16]) -> usize {
+        self.audio_mut().read_i16(out)
+    }
+
+    /// Starts recording the audio to a WAV file at `path`, and with `stems`
+    /// each channel to a file of its own next to it, see `Recorder`. The
+    /// files are written as `run_frame` goes along, if that fails the
+    /// recording stops and the error is kept for `take_recording_error`.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, stems: bool) -> io::Result<()> {
+        self.recording_error = None;
+        self.recorder = Some(Recorder::create(path.as_ref(), stems)?);
+        self.mmu.apu_mut().start_capture(RECORDING_SAMPLE_RATE, stems);
+        Ok(())
+    }
+
+    fn write_recording(&mut self) -> io::Result<()> {
+        match (self.recorder.as_mut(), self.mmu.apu_mut().capture_mut()) {
+            (Some(recorder), Some(capture)) => recorder.write(capture),
+            _ => Ok(())
+        }
+    }
+
+    /// The error that stopped the recording early, if writing it failed.
+    pub fn take_recording_error(&mut self) -> Option<io::Error> {
+        self.recording_error.take()
+    }
+
+    /// Finishes the recording, if one is running, or returns the error it
+    /// stopped on.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        let result = match (self.recorder.take(), self.mmu.apu_mut().stop_capture()) {
+            (Some(recorder), Some(mut capture)) => recorder.finish(&mut capture),
+            _ => Ok(())
+        };
+
+        match self.recording_error.take() {
+            Some(err) => Err(err),
+            None => result
+        }
+    }
+
+    /// Runs a single instruction and everything it clocks, returning the
+    /// cycles that took including any time the CPU was stalled by DMA.
+    pub fn step(&mut self) -> u32 {
+        self.cpu.step(&mut self.mmu);
+
+        let mut cycles = self.cpu.cycle_delay as u32;
+        self.mmu.tick(cycles);
+
+        // the rest of the system keeps running while VRAM DMA holds the CPU
+        loop {
+            let stall = self.mmu.take_stall_cycles();
+            if stall == 0 {
+                break;
+            }
+
+            self.mmu.tick(stall);
+            cycles += stall;
+        }
+
+        self.save_cycles += cycles;
+        if self.save_cycles >= SAVE_INTERVAL {
+            self.save_cycles -= SAVE_INTERVAL;
+
+            if self.mmu.cartridge_mut().take_dirty() {
+                if let Err(err) = self.save() {
+                    println!("warning: could not write save: {}", err);
+                }
+            }
+        }
+
+        let rumble = self.mmu.cartridge().rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
 
+            if let Some(callback) = self.rumble_callback.as_mut() {
+                callback(rumble);
+            }
+        }
+
+        cycles
+    }
+
+    /// Powers the system back on. The cartridge keeps its RAM, everything
+    /// else starts over, running the boot ROM if one was set or else
+    /// starting at the cartridge with the state it would have left.
     pub fn reset(&mut self) {
-        // todo: boot from an actual rom
-        self.cpu.set_af(0x01b0);
-        self.cpu.set_bc(0x0013);
-        self.cpu.set_de(0x01d8);
-        self.cpu.set_hl(0x014d);
+        self.cpu = CPU::new();
+        self.mmu.reset();
+
+        match self.boot_rom.clone() {
+            Some(boot_rom) => self.mmu.map_boot_rom(boot_rom),
+            None => self.skip_boot()
+        }
+    }
+
+    /// Sets up the registers the way the model's boot ROM leaves them and
+    /// starts at the cartridge entry point.
+    fn skip_boot(&mut self) {
+        let registers = self.model.boot_registers(self.mmu.cartridge());
+
+        self.cpu.pc = 0x0100;
+        self.cpu.set_af(registers.af);
+        self.cpu.set_bc(registers.bc);
+        self.cpu.set_de(registers.de);
+        self.cpu.set_hl(registers.hl);
         self.cpu.sp = 0xfffe;
 
-        self.rom[0xff05] = 0x00;
-        self.rom[0xff06] = 0x00;
-        self.rom[0xff07] = 0x00;
-        self.rom[0xff10] = 0x80;
-        self.rom[0xff11] = 0xBF;
-        self.rom[0xff12] = 0xF3;
-        self.rom[0xff14] = 0xBF;
-        self.rom[0xff16] = 0x3F;
-        self.rom[0xff17] = 0x00;
-        self.rom[0xff19] = 0x00;
-
-        self.rom[0xFF05] = 0x00;
-        self.rom[0xFF06] = 0x00;
-        self.rom[0xFF07] = 0x00;
-        self.rom[0xFF10] = 0x80;
-        self.rom[0xFF11] = 0xBF;
-        self.rom[0xFF12] = 0xF3;
-        self.rom[0xFF14] = 0xBF;
-        self.rom[0xFF16] = 0x3F;
-        self.rom[0xFF17] = 0x00;
-        self.rom[0xFF19] = 0xBF;
-        self.rom[0xFF1A] = 0x7F;
-        self.rom[0xFF1B] = 0xFF;
-        self.rom[0xFF1C] = 0x9F;
-        self.rom[0xFF1E] = 0xBF;
-        self.rom[0xFF20] = 0xFF;
-        self.rom[0xFF21] = 0x00;
-        self.rom[0xFF22] = 0x00;
-        self.rom[0xFF23] = 0xBF;
-        self.rom[0xFF24] = 0x77;
-        self.rom[0xFF25] = 0xF3;
-        self.rom[0xFF26] = 0xF1;//-GB, 0xF0-SGB ; NR52
-        self.rom[0xFF40] = 0x91;
-        self.rom[0xFF42] = 0x00;
-        self.rom[0xFF43] = 0x00;
-        self.rom[0xFF45] = 0x00;
-        self.rom[0xFF47] = 0xFC;
-        self.rom[0xFF48] = 0xFF;
-        self.rom[0xFF49] = 0xFF;
-        self.rom[0xFF4A] = 0x00;
-        self.rom[0xFF4B] = 0x00;
-        self.rom[0xFFFF] = 0x00;
-
-    }
-}
\ No newline at end of file
+        self.mmu.timer_mut().set_counter(self.model.boot_div());
+
+        // the APU ignores register writes until it is powered on
+        self.mmu.write(0xFF26, if self.model.is_sgb() { 0xF0 } else { 0xF1 }); // NR52
+
+        // NRx4 are written without the trigger bit so nothing starts playing
+        self.mmu.write(0xFF05, 0x00);
+        self.mmu.write(0xFF06, 0x00);
+        self.mmu.write(0xFF07, 0x00);
+        self.mmu.write(0xFF10, 0x80);
+        self.mmu.write(0xFF11, 0xBF);
+        self.mmu.write(0xFF12, 0xF3);
+        self.mmu.write(0xFF14, 0x3F);
+        self.mmu.write(0xFF16, 0x3F);
+        self.mmu.write(0xFF17, 0x00);
+        self.mmu.write(0xFF19, 0x3F);
+        self.mmu.write(0xFF1A, 0x7F);
+        self.mmu.write(0xFF1B, 0xFF);
+        self.mmu.write(0xFF1C, 0x9F);
+        self.mmu.write(0xFF1E, 0x3F);
+        self.mmu.write(0xFF20, 0xFF);
+        self.mmu.write(0xFF21, 0x00);
+        self.mmu.write(0xFF22, 0x00);
+        self.mmu.write(0xFF23, 0x3F);
+        self.mmu.write(0xFF24, 0x77);
+        self.mmu.write(0xFF25, 0xF3);
+        self.mmu.write(0xFF40, 0x91);
+        self.mmu.write(0xFF42, 0x00);
+        self.mmu.write(0xFF43, 0x00);
+        self.mmu.write(0xFF45, 0x00);
+        self.mmu.write(0xFF47, 0xFC);
+        self.mmu.write(0xFF48, 0xFF);
+        self.mmu.write(0xFF49, 0xFF);
+        self.mmu.write(0xFF4A, 0x00);
+        self.mmu.write(0xFF4B, 0x00);
+        self.mmu.write(0xFF0F, 0xE1);
+        self.mmu.write(0xFFFF, 0x00);
+    }
+}
+
+impl Drop for Gamelad {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop_recording() {
+            println!("warning: could not finish recording: {}", err);
+        }
+
+        if let Err(err) = self.save() {
+            println!("warning: could not write save: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot::DMG_BOOT_ROM_SIZE;
+    use crate::cartridge::header::{ fix_checksums, test_image };
+
+    /// A ROM only cartridge booted with a boot ROM that unmaps itself right
+    /// away with LD A, 1 and LDH (0x50), A.
+    fn booting() -> Gamelad {
+        let cartridge = Cartridge::from_bytes(test_image(b"BOOT", 0x00, 0x00, 0x00)).unwrap();
+        let mut gamelad = Gamelad::from_cartridge(cartridge, Some(Model::Dmg), RendererKind::Scanline, None);
+
+        let mut boot_rom = vec![0; DMG_BOOT_ROM_SIZE];
+        boot_rom[..4].copy_from_slice(&[0x3e, 0x01, 0xe0, 0x50]);
+        gamelad.set_boot_rom(BootRom::new(boot_rom).unwrap()).unwrap();
+
+        gamelad
+    }
+
+    #[test]
+    fn reset_runs_the_boot_rom_until_it_unmaps_itself() {
+        let mut gamelad = booting();
+        gamelad.reset();
+
+        assert_eq!(gamelad.cpu.pc, 0x0000);
+        assert!(gamelad.mmu.is_boot_rom_mapped());
+        assert_eq!(gamelad.mmu.read(0x0000), 0x3e);
+
+        gamelad.step();
+        assert_eq!(gamelad.cpu.pc, 0x0002);
+        assert!(gamelad.mmu.is_boot_rom_mapped());
+
+        gamelad.step();
+        assert_eq!(gamelad.cpu.pc, 0x0004);
+        assert!(!gamelad.mmu.is_boot_rom_mapped());
+        assert_eq!(gamelad.mmu.read(0x0000), gamelad.mmu.cartridge().read_rom(0x0000));
+    }
+
+    /// A DMG about to run STOP and then spin.
+    fn stopping() -> Gamelad {
+        let mut rom = test_image(b"STOP", 0x00, 0x00, 0x00);
+        // STOP, then JR -2
+        rom[0x0100..0x0104].copy_from_slice(&[0x10, 0x00, 0x18, 0xfe]);
+        fix_checksums(&mut rom);
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        let mut gamelad = Gamelad::from_cartridge(cartridge, Some(Model::Dmg), RendererKind::Scanline, None);
+        gamelad.reset();
+        gamelad
+    }
+
+    #[test]
+    fn a_button_press_ends_stop() {
+        let mut gamelad = stopping();
+
+        gamelad.step();
+        assert!(gamelad.is_stopped());
+
+        // A is not on a selected line yet
+        gamelad.set_button(Button::A, true);
+        gamelad.step();
+        assert!(gamelad.is_stopped());
+
+        gamelad.mmu.write(0xff00, 0x10);
+        gamelad.step();
+        gamelad.step();
+        assert!(!gamelad.is_stopped());
+        assert_eq!(gamelad.cpu.pc, 0x0102);
+    }
+
+    /// A DMG running a cartridge of `cartridge_type` and `rom_size` code,
+    /// each 16 KiB bank starting with its own number.
+    fn banked(cartridge_type: u8, rom_size: u8) -> Gamelad {
+        let mut rom = test_image(b"BANKS", cartridge_type, rom_size, 0x00);
+        for bank in 0..rom.len() / 0x4000 {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        fix_checksums(&mut rom);
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        let mut gamelad = Gamelad::from_cartridge(cartridge, Some(Model::Dmg), RendererKind::Scanline, None);
+        gamelad.reset();
+        gamelad
+    }
+
+    #[test]
+    fn reset_puts_mbc1_back_in_the_simple_mode() {
+        // 1 MiB, so the advanced mode banks the upper bits in at 0x0000
+        let mut gamelad = banked(0x01, 0x05);
+
+        gamelad.mmu.write(0x4000, 0x01);
+        gamelad.mmu.write(0x6000, 0x01);
+        assert_eq!(gamelad.mmu.read(0x0000), 0x20);
+
+        gamelad.reset();
+        assert_eq!(gamelad.mmu.read(0x0000), 0x00);
+        assert_eq!(gamelad.mmu.read(0x4000), 0x01);
+    }
+
+    #[test]
+    fn reset_unlocks_mmm01_back_to_the_menu() {
+        // 2 MiB, the menu sits in the last two banks
+        let mut gamelad = banked(0x0b, 0x06);
+        assert_eq!(gamelad.mmu.read(0x0000), 0x7e);
+
+        // the menu maps the game at bank 0x20 and locks it in
+        gamelad.mmu.write(0x2000, 0x20);
+        gamelad.mmu.write(0x0000, 0x40);
+        assert_eq!(gamelad.mmu.read(0x0000), 0x20);
+
+        gamelad.reset();
+        assert_eq!(gamelad.mmu.read(0x0000), 0x7e);
+        assert_eq!(gamelad.mmu.read(0x4000), 0x7f);
+    }
+
+    #[test]
+    fn a_pending_joypad_flag_does_not_end_stop() {
+        let mut gamelad = stopping();
+        gamelad.mmu.write(0xff00, 0x10);
+        gamelad.set_button(Button::A, true);
+        gamelad.set_button(Button::A, false);
+
+        // the press from before sits in IF, with IE leaving it there
+        gamelad.mmu.write(0xff0f, 0x10);
+        gamelad.mmu.write(0xffff, 0x00);
+
+        gamelad.step();
+        gamelad.step();
+        gamelad.step();
+        assert!(gamelad.is_stopped());
+
+        gamelad.set_button(Button::A, true);
+        gamelad.step();
+        assert!(!gamelad.is_stopped());
+    }
+
+    #[test]
+    fn reset_maps_the_boot_rom_again_on_a_fresh_mmu() {
+        let mut gamelad = booting();
+        gamelad.reset();
+
+        gamelad.step();
+        gamelad.step();
+        gamelad.mmu.write(0xff80, 0x42);
+
+        gamelad.reset();
+
+        assert_eq!(gamelad.cpu.pc, 0x0000);
+        assert!(gamelad.mmu.is_boot_rom_mapped());
+        assert_eq!(gamelad.mmu.read(0x0000), 0x3e);
+        assert_eq!(gamelad.mmu.read(0xff80), 0x00);
+    }
+
+    /// /dev/full takes the file being created but fails every write.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn a_failed_recording_write_stops_it_and_is_kept() {
+        let cartridge = Cartridge::from_bytes(test_image(b"RECORD", 0x00, 0x00, 0x00)).unwrap();
+        let mut gamelad = Gamelad::from_cartridge(cartridge, Some(Model::Dmg), RendererKind::Scanline, None);
+        gamelad.reset();
+        gamelad.start_recording("/dev/full", false).unwrap();
+
+        let mut frames = 0;
+        while gamelad.recorder.is_some() {
+            gamelad.run_frame();
+            frames += 1;
+            assert!(frames < 60);
+        }
+
+        assert!(gamelad.mmu.apu_mut().capture_mut().is_none());
+        assert!(gamelad.stop_recording().is_err());
+        assert!(gamelad.stop_recording().is_ok());
+    }
+}
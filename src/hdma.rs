@@ -0,0 +1,104 @@
+/// Bytes copied per HDMA block.
+pub const HDMA_BLOCK_SIZE: u16 = 0x10;
+
+/// How long a block keeps the CPU off the bus, in normal speed cycles.
+pub const HDMA_BLOCK_CYCLES: u32 = 32;
+
+/// The CGB VRAM DMA controller. It copies blocks of 16 bytes from ROM or
+/// RAM into the current VRAM bank, either all at once (general purpose DMA)
+/// or one block at the start of every HBlank (HBlank DMA).
+///
+/// | addr  | register                                       |
+/// |-------|------------------------------------------------|
+/// | FF51  | HDMA1, source bits 8-15                        |
+/// | FF52  | HDMA2, source bits 4-7                         |
+/// | FF53  | HDMA3, destination bits 8-12                   |
+/// | FF54  | HDMA4, destination bits 4-7                    |
+/// | FF55  | HDMA5, length in blocks minus one and mode     |
+///
+/// Writing HDMA5 with bit 7 clear starts a general purpose DMA, or cancels
+/// a running HBlank DMA. With bit 7 set it starts an HBlank DMA. Reading it
+/// back gives the blocks left minus one, with bit 7 set once the transfer
+/// is no longer running, so a finished transfer reads 0xFF.
+pub struct Hdma {
+    source: u16,
+    destination: u16,
+    /// Blocks left to copy.
+    remaining: u8,
+    hblank_active: bool
+}
+
+impl Hdma {
+    pub fn new() -> Hdma {
+        Hdma {
+            source: 0,
+            destination: 0,
+            remaining: 0,
+            hblank_active: false
+        }
+    }
+
+    pub fn hblank_active(&self) -> bool {
+        self.hblank_active
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0xff55 => {
+                let blocks = self.remaining.wrapping_sub(1) & 0x7f;
+                if self.hblank_active { blocks } else { 0x80 | blocks }
+            },
+            _ => 0xff
+        }
+    }
+
+    /// Returns the number of blocks to copy right away when the write starts
+    /// a general purpose DMA.
+    pub fn write_register(&mut self, addr: u16, value: u8) -> Option<u8> {
+        match addr {
+            0xff51 => self.source = (self.source & 0x00ff) | ((value as u16) << 8),
+            0xff52 => self.source = (self.source & 0xff00) | (value & 0xf0) as u16,
+            0xff53 => self.destination = (self.destination & 0x00ff) | (((value & 0x1f) as u16) << 8),
+            0xff54 => self.destination = (self.destination & 0xff00) | (value & 0xf0) as u16,
+            0xff55 => {
+                if self.hblank_active && value & 0x80 == 0 {
+                    self.hblank_active = false;
+                    return None;
+                }
+
+                self.remaining = (value & 0x7f) + 1;
+
+                if value & 0x80 != 0 {
+                    self.hblank_active = true;
+                } else {
+                    return Some(self.remaining);
+                }
+            },
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Takes the next block off the transfer, returning its source and VRAM
+    /// destination address.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.destination = (self.destination + HDMA_BLOCK_SIZE) & 0x1ff0;
+        self.remaining = self.remaining.saturating_sub(1);
+
+        if self.remaining == 0 {
+            self.hblank_active = false;
+        }
+
+        block
+    }
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Hdma::new()
+    }
+}
@@ -0,0 +1,181 @@
+use crate::cpu::interrupts::Interrupt;
+
+/// The eight buttons, four on the direction lines and four on the action
+/// lines of P1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Button {
+    Right, Left, Up, Down, A, B, Select, Start
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right, Button::Left, Button::Up, Button::Down,
+        Button::A, Button::B, Button::Select, Button::Start
+    ];
+
+    fn is_action(self) -> bool {
+        matches!(self, Button::A | Button::B | Button::Select | Button::Start)
+    }
+
+    /// The button's input line, as a bit of P1.
+    fn mask(self) -> u8 {
+        1 << (self as u8 & 0x03)
+    }
+}
+
+/// The buttons, read through P1 at 0xFF00.
+///
+/// | bit | function                                    |
+/// |-----|---------------------------------------------|
+/// | 5   | 0 selects the action buttons                |
+/// | 4   | 0 selects the direction buttons             |
+/// | 3   | Down or Start, 0 when pressed               |
+/// | 2   | Up or Select                                |
+/// | 1   | Left or B                                   |
+/// | 0   | Right or A                                  |
+///
+/// With both groups selected a line reads low when either of its buttons is
+/// pressed. A selected line going low raises the joypad interrupt and also
+/// ends STOP, see `take_press`.
+pub struct Joypad {
+    select: u8,
+    directions: u8,
+    actions: u8,
+    interrupts: u8,
+    /// A selected line went low since the last `take_press`.
+    pressed: bool
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0x30,
+            directions: 0,
+            actions: 0,
+            interrupts: 0,
+            pressed: false
+        }
+    }
+
+    /// Deselects both groups and drops pending interrupts, the buttons the
+    /// host is holding stay pressed.
+    pub fn reset(&mut self) {
+        self.select = 0x30;
+        self.interrupts = 0;
+        self.pressed = false;
+    }
+
+    /// Returns the interrupts raised since the last call as IF bits.
+    pub fn take_interrupts(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+        interrupts
+    }
+
+    /// Returns whether a selected line went low since the last call, which
+    /// is what wakes the CPU from STOP whatever IF and IE hold.
+    pub fn take_press(&mut self) -> bool {
+        let pressed = self.pressed;
+        self.pressed = false;
+        pressed
+    }
+
+    /// The lines pulled low by a pressed button in a selected group.
+    fn low_lines(&self) -> u8 {
+        let mut lines = 0;
+
+        if self.select & 0x10 == 0 {
+            lines |= self.directions;
+        }
+
+        if self.select & 0x20 == 0 {
+            lines |= self.actions;
+        }
+
+        lines
+    }
+
+    /// Raises the interrupt and notes the press if a line went low since
+    /// `before`.
+    fn update(&mut self, before: u8) {
+        if self.low_lines() & !before != 0 {
+            self.interrupts |= Interrupt::Joypad.mask();
+            self.pressed = true;
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let before = self.low_lines();
+        let group = if button.is_action() { &mut self.actions } else { &mut self.directions };
+
+        if pressed {
+            *group |= button.mask();
+        } else {
+            *group &= !button.mask();
+        }
+
+        self.update(before);
+    }
+
+    pub fn read_register(&self) -> u8 {
+        0xc0 | self.select | (!self.low_lines() & 0x0f)
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        let before = self.low_lines();
+        self.select = value & 0x30;
+        self.update(before);
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressed_buttons_read_low_in_their_selected_group() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Start, true);
+        joypad.set_button(Button::Left, true);
+
+        assert_eq!(joypad.read_register(), 0xff);
+
+        joypad.write_register(0x10);
+        assert_eq!(joypad.read_register(), 0xd7);
+
+        joypad.write_register(0x20);
+        assert_eq!(joypad.read_register(), 0xed);
+
+        joypad.write_register(0x00);
+        assert_eq!(joypad.read_register(), 0xc5);
+    }
+
+    #[test]
+    fn a_selected_line_going_low_raises_the_interrupt() {
+        let mut joypad = Joypad::new();
+
+        // nothing selected, nothing goes low
+        joypad.set_button(Button::A, true);
+        assert_eq!(joypad.take_interrupts(), 0);
+
+        // selecting the group with A held pulls its line low
+        joypad.write_register(0x10);
+        assert_eq!(joypad.take_interrupts(), Interrupt::Joypad.mask());
+        assert!(joypad.take_press());
+        assert!(!joypad.take_press());
+
+        joypad.set_button(Button::A, false);
+        joypad.set_button(Button::Down, true);
+        assert_eq!(joypad.take_interrupts(), 0);
+        assert!(!joypad.take_press());
+
+        joypad.set_button(Button::B, true);
+        assert_eq!(joypad.take_interrupts(), Interrupt::Joypad.mask());
+    }
+}
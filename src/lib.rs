@@ -1,3 +1,30 @@
+/// Prints an execution trace line when built with the `trace` feature.
+/// Otherwise the arguments are still type checked but nothing is printed,
+/// which keeps headless runs fast.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "trace") {
+            println!($($arg)*);
+        }
+    };
+}
 
+pub mod apu;
+pub mod boot;
+pub mod cartridge;
 pub mod cpu;
-pub mod gamelad;
\ No newline at end of file
+pub mod gamelad;
+pub mod hdma;
+pub mod joypad;
+pub mod mmu;
+pub mod model;
+pub mod ppu;
+pub mod recorder;
+pub mod ring_buffer;
+pub mod save;
+#[cfg(feature = "sdl")]
+pub mod sdl_audio;
+pub mod serial;
+pub mod test_rom;
+pub mod timer;
+pub mod wav;
@@ -1,6 +1,25 @@
+use gamelads::boot::BootRom;
+use gamelads::gamelad::Gamelad;
+use gamelads::ppu::RendererKind;
+use gamelads::test_rom::{ self, Outcome };
 
+use std::env;
+use std::process;
 
-use gamelads::gamelad::Gamelad;
+#[cfg(feature = "sdl")]
+use gamelads::cartridge::mbc::CLOCK_SPEED;
+#[cfg(feature = "sdl")]
+use gamelads::ppu::CYCLES_PER_FRAME;
+#[cfg(feature = "sdl")]
+use gamelads::sdl_audio::SdlAudio;
+#[cfg(feature = "sdl")]
+use sdl2::event::Event;
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use std::thread;
+#[cfg(feature = "sdl")]
+use std::time::{ Duration, Instant };
 
 // https://www.youtube.com/watch?v=HyzD8pNlpwI
 // https://gbdev.io/gb-opcodes//optables/
@@ -8,14 +27,147 @@ use gamelads::gamelad::Gamelad;
 // https://eldred.fr/gb-asm-tutorial/data_manip.html#ld
 // https://gbdev.io/pandocs/
 
+/// How long a test ROM gets before it counts as hung, about two minutes.
+const DEFAULT_TEST_FRAMES: u32 = 60 * 120;
+
+const USAGE: &str = "usage: gamelads [--trace | --play | --record FILE [--stems]] [--frames N] [ROM]
+
+Runs ROM, ./roms/cpu_instrs.gb by default, headless as a test ROM and
+exits with 0 if it passed.
+
+  --frames N     give up on the test after N frames, or record N frames
+  --record FILE  run the ROM headless and record its audio to a WAV file
+  --stems        also record each sound channel to FILE.ch1.wav and so on
+  --trace        run the ROM forever, tracing every instruction when built
+                 with the trace feature
+  --play         run the ROM in real time with sound, when built with the
+                 sdl feature";
+
+struct Options {
+    filename: String,
+    frames: u32,
+    trace: bool,
+    play: bool,
+    record: Option<String>,
+    stems: bool
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        filename: String::from("./roms/cpu_instrs.gb"),
+        frames: DEFAULT_TEST_FRAMES,
+        trace: false,
+        play: false,
+        record: None,
+        stems: false
+    };
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => options.trace = true,
+            "--play" => options.play = true,
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file name")?),
+            "--stems" => options.stems = true,
+            "--frames" => {
+                let frames = args.next().ok_or("--frames needs a count")?;
+                options.frames = frames.parse().map_err(|_| format!("bad frame count {}", frames))?;
+            },
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ => options.filename = arg
+        }
+    }
+
+    Ok(options)
+}
+
+/// Runs the ROM at its real speed with the audio going to SDL, until SDL
+/// is asked to quit or Escape is pressed.
+#[cfg(feature = "sdl")]
+fn play(gamelad: &mut Gamelad) -> Result<(), String> {
+    let sdl = sdl2::init()?;
+    let mut audio = SdlAudio::open(&sdl.audio()?)?;
+    let mut events = sdl.event_pump()?;
+
+    let frame_time = Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / CLOCK_SPEED as f64);
+    let mut next_frame = Instant::now();
+
+    gamelad.reset();
+
+    loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(()),
+                _ => {}
+            }
+        }
+
+        gamelad.run_frame();
+        audio.queue(gamelad);
+
+        next_frame += frame_time;
+        if let Some(wait) = next_frame.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn play(_gamelad: &mut Gamelad) -> Result<(), String> {
+    Err(String::from("--play needs a build with the sdl feature"))
+}
+
+/// Runs the ROM for `frames` frames as fast as it goes, recording the audio.
+fn record(gamelad: &mut Gamelad, path: &str, stems: bool, frames: u32) -> Result<(), String> {
+    gamelad.reset();
+    gamelad.start_recording(path, stems).map_err(|err| err.to_string())?;
+
+    for _ in 0..frames {
+        gamelad.run_frame();
+
+        if let Some(err) = gamelad.take_recording_error() {
+            return Err(format!("could not write {}: {}", path, err));
+        }
+    }
+
+    gamelad.stop_recording().map_err(|err| err.to_string())?;
+    println!("recorded {} frames to {}", frames, path);
+
+    Ok(())
+}
 
 fn main() -> Result<(), String> {
+    let options = parse_options()?;
+
+    let mut gamelad = Gamelad::new(&options.filename, None, RendererKind::Scanline).map_err(|err| err.to_string())?;
+
+    // boot for real if a dump for the model sits next to the test ROMs
+    if let Ok(boot_rom) = BootRom::for_model("./roms", gamelad.model()) {
+        gamelad.set_boot_rom(boot_rom).map_err(|err| err.to_string())?;
+    }
+
+    if options.trace {
+        gamelad.run();
+        println!("locked up on an illegal instruction");
+        return Ok(());
+    }
+
+    if options.play {
+        return play(&mut gamelad);
+    }
+
+    if let Some(path) = options.record.as_ref() {
+        return record(&mut gamelad, path, options.stems, options.frames);
+    }
 
-    let filename = "./roms/cpu_instrs.gb";//"./asm/bin/load_reg";
-    
-    let mut gamelad = Gamelad::new(filename);
+    let report = test_rom::run(&mut gamelad, options.frames);
+    println!("{}", report);
 
-    gamelad.run();
+    if report.outcome != Outcome::Passed {
+        process::exit(1);
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,548 @@
+use crate::apu::Apu;
+use crate::boot::BootRom;
+use crate::cartridge::Cartridge;
+use crate::hdma::{ Hdma, HDMA_BLOCK_CYCLES, HDMA_BLOCK_SIZE };
+use crate::joypad::Joypad;
+use crate::ppu::OAM_SIZE;
+use crate::model::Model;
+use crate::ppu::{ Mode, Ppu, RendererKind };
+use crate::serial::Serial;
+use crate::timer::Timer;
+
+/// Anything the CPU can read from and write to through the 16 bit address bus.
+pub trait Bus {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Called when the CPU executes STOP. Performs a speed switch prepared
+    /// through KEY1 and returns whether there was one.
+    fn speed_switch(&mut self) -> bool {
+        false
+    }
+
+    /// Returns whether a button press pulled a selected joypad line low
+    /// since the last call, which ends STOP.
+    fn take_joypad_press(&mut self) -> bool {
+        false
+    }
+}
+
+pub const WRAM_SIZE: usize = 0x2000;
+pub const CGB_WRAM_SIZE: usize = 0x8000;
+pub const WRAM_BANK_SIZE: usize = 0x1000;
+pub const IO_SIZE: usize = 0x80;
+pub const HRAM_SIZE: usize = 0x7f;
+
+/// OAM DMA copies one byte per M-cycle.
+const OAM_DMA_BYTE_CYCLES: u32 = 4;
+
+/// An OAM DMA in progress. It starts one M-cycle after the write to 0xFF46
+/// and then copies a byte every M-cycle for 640 cycles.
+struct OamDma {
+    source: u16,
+    copied: u16,
+    cycles: u32,
+    /// Set once the transfer holds the bus, which an earlier transfer that
+    /// is being restarted already does.
+    blocking: bool,
+    started: bool
+}
+
+/// Maps the cartridge, the internal memories and the I/O registers into the
+/// 64 KiB address space.
+///
+/// | range       | region                          |
+/// |-------------|---------------------------------|
+/// | 0000-7FFF   | cartridge ROM                   |
+/// | 8000-9FFF   | video RAM                       |
+/// | A000-BFFF   | external (cartridge) RAM        |
+/// | C000-CFFF   | work RAM bank 0                 |
+/// | D000-DFFF   | work RAM bank 1-7               |
+/// | E000-FDFF   | echo of C000-DDFF               |
+/// | FE00-FE9F   | object attribute memory         |
+/// | FEA0-FEFF   | unusable                        |
+/// | FF00-FF7F   | I/O registers                   |
+/// | FF80-FFFE   | high RAM                        |
+/// | FFFF        | interrupt enable                |
+///
+/// In CGB mode 0xFF70 (SVBK) picks the work RAM bank at 0xD000, where
+/// selecting bank 0 gives bank 1, and 0xFF4D (KEY1) prepares a switch to or
+/// from double speed that the next STOP carries out. Bit 7 of KEY1 reads
+/// back the current speed. 0xFF51-0xFF55 drive the VRAM DMA, see `Hdma`.
+///
+/// Writing 0xFF46 copies 160 bytes from the page it names into OAM. While
+/// that runs the CPU can't reach OAM or the bus the source is on, which is
+/// either the video RAM or the one the cartridge and work RAM share. Reads
+/// from those give 0xFF and writes are dropped, the other bus, the I/O
+/// registers and high RAM stay open.
+///
+/// While a boot ROM is mapped it hides the start of the cartridge ROM,
+/// writing a non-zero value to 0xFF50 unmaps it for good.
+pub struct Mmu {
+    cartridge: Cartridge,
+    model: Model,
+    renderer: RendererKind,
+    boot_rom: Option<BootRom>,
+    ppu: Ppu,
+    apu: Apu,
+    timer: Timer,
+    serial: Serial,
+    joypad: Joypad,
+    cgb: bool,
+    wram: Vec<u8>,
+    wram_bank: u8,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    hdma: Hdma,
+    oam_dma: Option<OamDma>,
+    /// CPU cycles the CPU has to sit out while VRAM DMA holds the bus.
+    stall_cycles: u32,
+    io: Vec<u8>,
+    hram: Vec<u8>,
+    ie: u8
+}
+
+impl Mmu {
+    pub fn new(cartridge: Cartridge, model: Model, renderer: RendererKind) -> Mmu {
+        let cgb = model.cgb_mode(&cartridge);
+
+        Mmu {
+            cartridge,
+            model,
+            renderer,
+            boot_rom: None,
+            ppu: Ppu::new(renderer, model, cgb),
+            apu: Apu::new(model),
+            timer: Timer::new(),
+            serial: Serial::new(model.is_cgb()),
+            joypad: Joypad::new(),
+            cgb,
+            wram: vec![0; if cgb { CGB_WRAM_SIZE } else { WRAM_SIZE }],
+            wram_bank: 1,
+            double_speed: false,
+            speed_switch_armed: false,
+            hdma: Hdma::new(),
+            oam_dma: None,
+            stall_cycles: 0,
+            io: vec![0; IO_SIZE],
+            hram: vec![0; HRAM_SIZE],
+            ie: 0
+        }
+    }
+
+    /// Puts everything back the way `new` left it, except that the
+    /// cartridge keeps its RAM and clock, and the APU's output and capture
+    /// and the held buttons, which belong to the host, stay as they are.
+    pub fn reset(&mut self) {
+        self.cartridge.reset();
+        self.boot_rom = None;
+        self.ppu = Ppu::new(self.renderer, self.model, self.cgb);
+        self.apu.reset();
+        self.timer = Timer::new();
+        self.serial = Serial::new(self.model.is_cgb());
+        self.joypad.reset();
+        self.wram.fill(0);
+        self.wram_bank = 1;
+        self.double_speed = false;
+        self.speed_switch_armed = false;
+        self.hdma = Hdma::new();
+        self.oam_dma = None;
+        self.stall_cycles = 0;
+        self.io.fill(0);
+        self.hram.fill(0);
+        self.ie = 0;
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    pub fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
+    /// Maps `boot_rom` over the cartridge until 0xFF50 is written.
+    pub fn map_boot_rom(&mut self, boot_rom: BootRom) {
+        self.boot_rom = Some(boot_rom);
+    }
+
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    /// Where `addr` in work RAM or its echo lands in the banked WRAM.
+    fn wram_index(&self, addr: usize) -> usize {
+        let offset = addr & 0x1fff;
+
+        if offset < WRAM_BANK_SIZE {
+            offset
+        } else {
+            self.wram_bank as usize * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+        }
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Returns the cycles the CPU has been stalled for since the last call.
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        let cycles = self.stall_cycles;
+        self.stall_cycles = 0;
+        cycles
+    }
+
+    /// Copies the next VRAM DMA block and stalls the CPU for it. The copy
+    /// runs at normal speed, so it takes twice the CPU cycles in double speed.
+    fn copy_hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+
+        for offset in 0..HDMA_BLOCK_SIZE {
+            let value = self.read_direct(source.wrapping_add(offset));
+            self.ppu.write_vram(destination + offset, value);
+        }
+
+        self.stall_cycles += if self.double_speed { HDMA_BLOCK_CYCLES * 2 } else { HDMA_BLOCK_CYCLES };
+    }
+
+    fn oam_dma_blocking(&self) -> bool {
+        self.oam_dma.as_ref().is_some_and(|dma| dma.blocking)
+    }
+
+    /// Whether a running OAM DMA keeps the CPU from `addr`.
+    fn oam_dma_blocks(&self, addr: u16) -> bool {
+        let on_vram_bus = |addr: u16| (0x8000..0xa000).contains(&addr);
+
+        match self.oam_dma.as_ref() {
+            Some(dma) if dma.blocking => match addr {
+                0xfe00..=0xfeff => true,
+                0xff00..=0xffff => false,
+                _ => on_vram_bus(addr) == on_vram_bus(dma.source)
+            },
+            _ => false
+        }
+    }
+
+    fn start_oam_dma(&mut self, page: u8) {
+        // the pages past work RAM read its echo
+        let page = if page >= 0xe0 { page - 0x20 } else { page };
+
+        self.oam_dma = Some(OamDma {
+            source: (page as u16) << 8,
+            copied: 0,
+            cycles: 0,
+            blocking: self.oam_dma_blocking(),
+            started: false
+        });
+    }
+
+    /// Copies the OAM DMA bytes due over `cycles`, which run at CPU speed.
+    fn tick_oam_dma(&mut self, cycles: u32) {
+        let mut dma = match self.oam_dma.take() {
+            Some(dma) => dma,
+            None => return
+        };
+
+        dma.cycles += cycles;
+
+        while dma.cycles >= OAM_DMA_BYTE_CYCLES {
+            dma.cycles -= OAM_DMA_BYTE_CYCLES;
+
+            if !dma.started {
+                dma.started = true;
+                dma.blocking = true;
+                continue;
+            }
+
+            let value = self.read_direct(dma.source + dma.copied);
+            self.ppu.write_oam(0xfe00 + dma.copied, value);
+            dma.copied += 1;
+
+            if dma.copied as usize == OAM_SIZE {
+                return;
+            }
+        }
+
+        self.oam_dma = Some(dma);
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        for _ in 0..self.timer.take_apu_clocks() {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    /// Advances everything on the bus by the cycles the CPU just spent. In
+    /// double speed those cycles only take half as long, so the PPU, the APU
+    /// and the cartridge clock see half as many, while the timer keeps up
+    /// with the CPU.
+    pub fn tick(&mut self, cycles: u32) {
+        let normal_cycles = if self.double_speed { cycles / 2 } else { cycles };
+
+        self.cartridge.tick(normal_cycles);
+        self.ppu.tick(normal_cycles);
+        self.timer.tick(cycles);
+        self.clock_frame_sequencer();
+        self.apu.tick(normal_cycles);
+        self.serial.tick(cycles);
+        self.tick_oam_dma(cycles);
+
+        if self.ppu.take_hblank() && self.hdma.hblank_active() {
+            self.copy_hdma_block();
+        }
+
+        self.io[0x0f] |= self.ppu.take_interrupts() | self.timer.take_interrupts() | self.serial.take_interrupts()
+            | self.joypad.take_interrupts();
+    }
+
+    /// Reads as the DMA controllers see the bus, without OAM DMA locking
+    /// the CPU out.
+    fn read_direct(&self, addr: u16) -> u8 {
+        let addr = addr as usize;
+
+        if let Some(value) = self.boot_rom.as_ref().and_then(|boot_rom| boot_rom.read(addr as u16)) {
+            return value;
+        }
+
+        match addr {
+            0x0000..=0x7fff => self.cartridge.read_rom(addr as u16),
+            0x8000..=0x9fff => self.ppu.read_vram(addr as u16),
+            0xa000..=0xbfff => self.cartridge.read_ram(addr as u16),
+            0xc000..=0xfdff => self.wram[self.wram_index(addr)],
+            0xfe00..=0xfe9f => self.ppu.read_oam(addr as u16),
+            0xfea0..=0xfeff => 0x00,
+            // the upper three bits of IF are unused and always read back set
+            0xff0f => self.io[0x0f] | 0xe0,
+            // the boot ROM lock is write only
+            0xff50 => 0xff,
+            0xff00 => self.joypad.read_register(),
+            0xff04..=0xff07 => self.timer.read_register(addr as u16),
+            0xff01..=0xff02 => self.serial.read_register(addr as u16),
+            0xff10..=0xff3f => self.apu.read_register(addr as u16),
+            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f | 0xff68..=0xff6c => self.ppu.read_register(addr as u16),
+            0xff70 if self.cgb => 0xf8 | self.wram_bank,
+            0xff4d if self.cgb => 0x7e | ((self.double_speed as u8) << 7) | self.speed_switch_armed as u8,
+            0xff4d => 0xff,
+            0xff51..=0xff55 if self.cgb => self.hdma.read_register(addr as u16),
+            0xff03..=0xff7f => self.io[addr - 0xff00],
+            0xff80..=0xfffe => self.hram[addr - 0xff80],
+            0xffff => self.ie,
+            _ => unreachable!()
+        }
+    }
+
+    fn write_direct(&mut self, addr: u16, value: u8) {
+        let addr = addr as usize;
+
+        match addr {
+            0x0000..=0x7fff => self.cartridge.write_rom(addr as u16, value),
+            0x8000..=0x9fff => self.ppu.write_vram(addr as u16, value),
+            0xa000..=0xbfff => self.cartridge.write_ram(addr as u16, value),
+            0xc000..=0xfdff => {
+                let index = self.wram_index(addr);
+                self.wram[index] = value;
+            },
+            0xfe00..=0xfe9f => self.ppu.write_oam(addr as u16, value),
+            0xfea0..=0xfeff => {},
+            0xff50 => if value != 0 {
+                self.boot_rom = None;
+            },
+            0xff00 => self.joypad.write_register(value),
+            0xff04..=0xff07 => {
+                self.timer.write_register(addr as u16, value);
+                self.clock_frame_sequencer();
+            },
+            0xff01..=0xff02 => self.serial.write_register(addr as u16, value),
+            0xff10..=0xff3f => self.apu.write_register(addr as u16, value),
+            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f | 0xff68..=0xff6c => self.ppu.write_register(addr as u16, value),
+            0xff70 if self.cgb => self.wram_bank = (value & 0x07).max(1),
+            0xff4d if self.cgb => self.speed_switch_armed = value & 0x01 != 0,
+            0xff51..=0xff55 if self.cgb => {
+                if let Some(blocks) = self.hdma.write_register(addr as u16, value) {
+                    for _ in 0..blocks {
+                        self.copy_hdma_block();
+                    }
+                }
+
+                // with the LCD off there is no HBlank to wait for, and one
+                // already under way doesn't wait for the next
+                let in_hblank = !self.ppu.lcd_enabled() || self.ppu.mode() == Mode::HBlank;
+
+                if addr == 0xff55 && self.hdma.hblank_active() && in_hblank {
+                    self.copy_hdma_block();
+                }
+            },
+            0xff46 => {
+                self.io[0x46] = value;
+                self.start_oam_dma(value);
+            },
+            0xff03..=0xff7f => self.io[addr - 0xff00] = value,
+            0xff80..=0xfffe => self.hram[addr - 0xff80] = value,
+            0xffff => self.ie = value,
+            _ => unreachable!()
+        }
+    }
+}
+
+impl Bus for Mmu {
+    fn read(&self, addr: u16) -> u8 {
+        if self.oam_dma_blocks(addr) {
+            return 0xff;
+        }
+
+        self.read_direct(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if self.oam_dma_blocks(addr) {
+            return;
+        }
+
+        self.write_direct(addr, value);
+    }
+
+    fn speed_switch(&mut self) -> bool {
+        if !self.cgb || !self.speed_switch_armed {
+            return false;
+        }
+
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+
+        // the divider is reset as the clock changes
+        self.timer.set_double_speed(self.double_speed);
+        self.timer.write_register(0xff04, 0);
+        self.clock_frame_sequencer();
+        true
+    }
+
+    fn take_joypad_press(&mut self) -> bool {
+        self.joypad.take_press()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::header::{ fix_checksums, test_image };
+
+    /// An Mmu for a CGB with a cartridge made for it.
+    fn cgb_mmu() -> Mmu {
+        let mut rom = test_image(b"MMU", 0x00, 0x00, 0x00);
+        // CGB only
+        rom[0x0143] = 0xc0;
+        fix_checksums(&mut rom);
+
+        Mmu::new(Cartridge::from_bytes(rom).unwrap(), Model::Cgb, RendererKind::Scanline)
+    }
+
+    /// Ticks until the PPU is in `mode`.
+    fn run_until(mmu: &mut Mmu, mode: Mode) {
+        while mmu.ppu().mode() != mode {
+            mmu.tick(4);
+        }
+    }
+
+    /// Sets up an HBlank DMA of one block from 0xC000 to 0x8000.
+    fn start_hblank_dma(mmu: &mut Mmu) {
+        for offset in 0..HDMA_BLOCK_SIZE {
+            mmu.write(0xc000 + offset, offset as u8 + 1);
+        }
+
+        mmu.write(0xff51, 0xc0);
+        mmu.write(0xff52, 0x00);
+        mmu.write(0xff53, 0x80);
+        mmu.write(0xff54, 0x00);
+        mmu.write(0xff55, 0x80);
+    }
+
+    #[test]
+    fn hblank_dma_started_in_hblank_copies_a_block_right_away() {
+        let mut mmu = cgb_mmu();
+        mmu.write(0xff40, 0x91);
+        run_until(&mut mmu, Mode::HBlank);
+
+        start_hblank_dma(&mut mmu);
+
+        assert_eq!(mmu.ppu().read_vram(0x8000), 0x01);
+        assert_eq!(mmu.ppu().read_vram(0x800f), 0x10);
+        assert_eq!(mmu.read(0xff55), 0xff);
+    }
+
+    #[test]
+    fn hblank_dma_started_outside_hblank_waits_for_it() {
+        let mut mmu = cgb_mmu();
+        mmu.write(0xff40, 0x91);
+        run_until(&mut mmu, Mode::Drawing);
+
+        start_hblank_dma(&mut mmu);
+        assert_eq!(mmu.ppu().read_vram(0x8000), 0x00);
+
+        run_until(&mut mmu, Mode::HBlank);
+        assert_eq!(mmu.ppu().read_vram(0x8000), 0x01);
+        assert_eq!(mmu.ppu().read_vram(0x800f), 0x10);
+    }
+
+    #[test]
+    fn oam_dma_blocks_only_the_bus_it_reads_from() {
+        let mut mmu = cgb_mmu();
+        mmu.write(0x8000, 0x12);
+        mmu.write(0xc000, 0x34);
+        mmu.write(0xff80, 0x56);
+
+        // from work RAM the cartridge and work RAM are taken
+        mmu.write(0xff46, 0xc0);
+        mmu.tick(8);
+        assert_eq!(mmu.read(0x8000), 0x12);
+        assert_eq!(mmu.read(0xc000), 0xff);
+        assert_eq!(mmu.read(0x0104), 0xff);
+        assert_eq!(mmu.read(0xfe00), 0xff);
+        assert_eq!(mmu.read(0xff80), 0x56);
+
+        mmu.write(0x8001, 0x78);
+        mmu.write(0xc001, 0x78);
+        assert_eq!(mmu.ppu().read_vram(0x8001), 0x78);
+
+        // from video RAM only video RAM is
+        mmu.tick(640);
+        mmu.write(0xff46, 0x80);
+        mmu.tick(8);
+        assert_eq!(mmu.read(0x8000), 0xff);
+        assert_eq!(mmu.read(0xc000), 0x34);
+        assert_eq!(mmu.read(0xc001), 0x00);
+        assert_eq!(mmu.read(0x0104), 0xce);
+        assert_eq!(mmu.read(0xfe00), 0xff);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::header::{ CgbFlag, Licensee };
+
+use std::fmt;
+
+/// The Game Boy hardware revision being emulated. Games tell them apart by
+/// the register values the boot ROM leaves behind, mostly A and B.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Model {
+    /// The original DMG with the early boot ROM revision.
+    Dmg0,
+    Dmg,
+    /// Game Boy Pocket and Light.
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    /// A Game Boy Advance running Game Boy software.
+    Agb
+}
+
+/// CPU registers on leaving the boot ROM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BootRegisters {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16
+}
+
+impl Model {
+    /// Picks the most capable model the cartridge supports: colour games
+    /// run on a CGB, Super Game Boy enhanced games on an SGB and everything
+    /// else on a DMG.
+    pub fn for_cartridge(cartridge: &Cartridge) -> Model {
+        let header = cartridge.header();
+
+        if header.cgb_flag != CgbFlag::None {
+            Model::Cgb
+        // the SGB ignores its flag unless the old licensee code defers to the new one
+        } else if header.sgb_flag && header.old_licensee_code == 0x33 {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// Whether `cartridge` runs in CGB mode. CGB models run older games in
+    /// a DMG compatibility mode instead.
+    pub fn cgb_mode(self, cartridge: &Cartridge) -> bool {
+        self.is_cgb() && cartridge.header().cgb_flag != CgbFlag::None
+    }
+
+    /// The file name boot ROM dumps of this model conventionally go by.
+    pub fn boot_rom_name(self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0_boot.bin",
+            Model::Dmg => "dmg_boot.bin",
+            Model::Mgb => "mgb_boot.bin",
+            Model::Sgb => "sgb_boot.bin",
+            Model::Sgb2 => "sgb2_boot.bin",
+            Model::Cgb => "cgb_boot.bin",
+            Model::Agb => "agb_boot.bin"
+        }
+    }
+
+    /// The registers the boot ROM hands over to `cartridge` with.
+    pub fn boot_registers(self, cartridge: &Cartridge) -> BootRegisters {
+        let header = cartridge.header();
+
+        // H and C are left over from the header checksum loop
+        let checksum_flags = if header.header_checksum == 0 { 0x80 } else { 0xb0 };
+
+        match self {
+            Model::Dmg0 => BootRegisters { af: 0x0100, bc: 0xff13, de: 0x00c1, hl: 0x8403 },
+            Model::Dmg => BootRegisters { af: 0x0100 | checksum_flags, bc: 0x0013, de: 0x00d8, hl: 0x014d },
+            Model::Mgb => BootRegisters { af: 0xff00 | checksum_flags, bc: 0x0013, de: 0x00d8, hl: 0x014d },
+            Model::Sgb => BootRegisters { af: 0x0100, bc: 0x0014, de: 0x0000, hl: 0xc060 },
+            Model::Sgb2 => BootRegisters { af: 0xff00, bc: 0x0014, de: 0x0000, hl: 0xc060 },
+            Model::Cgb | Model::Agb => {
+                // the AGB boot ROM ends with an extra INC B
+                let agb = (self == Model::Agb) as u16;
+                let f = if self == Model::Agb { 0x00 } else { 0x80 };
+
+                if !self.cgb_mode(cartridge) {
+                    // B is the title checksum the boot ROM uses to pick a palette
+                    let b = Model::title_checksum(cartridge) as u16 + agb;
+
+                    BootRegisters { af: 0x1100 | f, bc: b << 8, de: 0x0008, hl: 0x007c }
+                } else {
+                    BootRegisters { af: 0x1100 | f, bc: agb << 8, de: 0xff56, hl: 0x000d }
+                }
+            }
+        }
+    }
+
+    /// The internal 16 bit divider on leaving the boot ROM, DIV reads back
+    /// the upper byte. Only the DMG revisions have been measured, the others
+    /// start from zero.
+    pub fn boot_div(self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x182c,
+            Model::Dmg | Model::Mgb => 0xabcc,
+            _ => 0x0000
+        }
+    }
+
+    /// The sum of the title bytes, only computed for Nintendo published games.
+    fn title_checksum(cartridge: &Cartridge) -> u8 {
+        let header = cartridge.header();
+
+        let nintendo = match header.licensee() {
+            Licensee::Old(code) => code == 0x01,
+            Licensee::New(code) => code == "01"
+        };
+
+        if !nintendo {
+            return 0;
+        }
+
+        cartridge.rom()[0x0134..0x0144].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Model::Dmg0 => "DMG0",
+            Model::Dmg => "DMG",
+            Model::Mgb => "MGB",
+            Model::Sgb => "SGB",
+            Model::Sgb2 => "SGB2",
+            Model::Cgb => "CGB",
+            Model::Agb => "AGB"
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,655 @@
+pub mod fifo;
+pub mod scanline;
+
+use crate::cpu::interrupts::Interrupt;
+use crate::model::Model;
+use crate::ppu::fifo::FifoRenderer;
+use crate::ppu::scanline::ScanlineRenderer;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const VRAM_SIZE: usize = 0x2000;
+pub const OAM_SIZE: usize = 0xa0;
+
+pub const DOTS_PER_LINE: u32 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+pub const OAM_SCAN_DOTS: u32 = 80;
+pub const CYCLES_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32;
+
+pub const LCDC_ENABLE: u8 = 0x80;
+pub const LCDC_WINDOW_MAP: u8 = 0x40;
+pub const LCDC_WINDOW_ENABLE: u8 = 0x20;
+pub const LCDC_TILE_DATA: u8 = 0x10;
+pub const LCDC_BG_MAP: u8 = 0x08;
+pub const LCDC_OBJ_SIZE: u8 = 0x04;
+pub const LCDC_OBJ_ENABLE: u8 = 0x02;
+pub const LCDC_BG_ENABLE: u8 = 0x01;
+
+pub const OBJ_BEHIND_BG: u8 = 0x80;
+pub const OBJ_Y_FLIP: u8 = 0x40;
+pub const OBJ_X_FLIP: u8 = 0x20;
+pub const OBJ_PALETTE: u8 = 0x10;
+pub const OBJ_CGB_BANK: u8 = 0x08;
+pub const OBJ_CGB_PALETTE: u8 = 0x07;
+
+/// BG map attributes in VRAM bank 1 use the same bits as objects, except
+/// that bit 7 gives the background priority over every object.
+pub const BG_PRIORITY: u8 = 0x80;
+
+pub const STAT_LYC_INTERRUPT: u8 = 0x40;
+pub const STAT_OAM_INTERRUPT: u8 = 0x20;
+pub const STAT_VBLANK_INTERRUPT: u8 = 0x10;
+pub const STAT_HBLANK_INTERRUPT: u8 = 0x08;
+pub const STAT_LYC_EQUAL: u8 = 0x04;
+
+/// Hardware limit on objects per line, the rest are dropped during OAM scan.
+pub const SPRITES_PER_LINE: usize = 10;
+
+/// Bytes of CGB palette RAM for each of background and objects, 8 palettes
+/// of 4 little endian 15 bit colors.
+pub const PALETTE_RAM_SIZE: usize = 0x40;
+
+/// The four DMG shades from white to black as 15 bit colors.
+pub const DMG_COLORS: [u16; 4] = [0x7fff, 0x56b5, 0x294a, 0x0000];
+
+/// The mode number the PPU reports in the low bits of STAT.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3
+}
+
+/// An OAM entry selected for the current line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    pub index: u8
+}
+
+/// A background or window pixel before palette lookup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct BgPixel {
+    pub color: u8,
+    /// The map attributes, always 0 outside CGB mode.
+    pub attributes: u8
+}
+
+/// An object pixel before palette lookup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct ObjPixel {
+    pub color: u8,
+    pub attributes: u8,
+    /// OAM index of the object, which decides overlaps in CGB mode.
+    pub index: u8
+}
+
+/// Which renderer draws mode 3, trading accuracy for speed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RendererKind {
+    /// Whole lines at once, see `ScanlineRenderer`.
+    #[default]
+    Scanline,
+    /// Dot by dot through the pixel FIFO, see `FifoRenderer`.
+    PixelFifo
+}
+
+/// Produces the pixels of mode 3. A renderer is handed the line once OAM
+/// scan is done and then clocked one dot at a time until it reports the
+/// line finished, which is when HBlank starts.
+pub trait Renderer {
+    fn start_line(&mut self, video: &mut Video);
+
+    /// Returns true once the last pixel of the line has been output.
+    fn tick(&mut self, video: &mut Video) -> bool;
+}
+
+/// Everything the renderers draw from: video RAM, OAM, the LCD registers
+/// and the framebuffer being drawn into.
+pub struct Video {
+    /// Whether the cartridge runs in CGB mode, rather than a DMG or a CGB in
+    /// DMG compatibility mode.
+    cgb: bool,
+    /// Two banks on the CGB, bank 1 holds more tiles and the map attributes.
+    vram: Vec<u8>,
+    vram_bank: u8,
+    oam: Vec<u8>,
+    lcdc: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    bcps: u8,
+    ocps: u8,
+    bg_palettes: [u8; PALETTE_RAM_SIZE],
+    obj_palettes: [u8; PALETTE_RAM_SIZE],
+    /// OPRI, bit 0 set falls back to DMG style object priority.
+    opri: u8,
+    /// The objects found by OAM scan for this line, in OAM order.
+    sprites: Vec<Sprite>,
+    /// The window keeps its own line counter that only advances on lines
+    /// it was actually drawn on, which the renderer flags in `window_drawn`.
+    window_line: u8,
+    window_drawn: bool,
+    framebuffer: Vec<u16>
+}
+
+impl Video {
+    fn new(cgb: bool) -> Video {
+        let banks = if cgb { 2 } else { 1 };
+
+        Video {
+            cgb,
+            vram: vec![0; VRAM_SIZE * banks],
+            vram_bank: 0,
+            oam: vec![0; OAM_SIZE],
+            lcdc: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            bcps: 0,
+            ocps: 0,
+            // the boot ROM leaves every background color white
+            bg_palettes: [0xff; PALETTE_RAM_SIZE],
+            obj_palettes: [0; PALETTE_RAM_SIZE],
+            opri: 0,
+            sprites: Vec::with_capacity(SPRITES_PER_LINE),
+            window_line: 0,
+            window_drawn: false,
+            framebuffer: vec![DMG_COLORS[0]; SCREEN_WIDTH * SCREEN_HEIGHT]
+        }
+    }
+
+    fn lcdc(&self, bit: u8) -> bool {
+        self.lcdc & bit != 0
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc(LCDC_OBJ_SIZE) { 16 } else { 8 }
+    }
+
+    /// Whether overlapping objects are decided by OAM index alone, as in
+    /// CGB mode, rather than by X coordinate first.
+    fn index_priority(&self) -> bool {
+        self.cgb && self.opri & 0x01 == 0
+    }
+
+    /// Picks the first ten objects in OAM that overlap the current line.
+    fn scan_oam(&mut self) {
+        let height = self.sprite_height() as u16;
+        let line = self.ly as u16 + 16;
+
+        self.sprites.clear();
+
+        for index in 0..OAM_SIZE / 4 {
+            let entry = &self.oam[index * 4..index * 4 + 4];
+            let y = entry[0] as u16;
+
+            if line >= y && line < y + height {
+                self.sprites.push(Sprite {
+                    y: entry[0],
+                    x: entry[1],
+                    tile: entry[2],
+                    attributes: entry[3],
+                    index: index as u8
+                });
+
+                if self.sprites.len() == SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Whether the window covers part of the current line.
+    fn window_visible(&self) -> bool {
+        self.lcdc(LCDC_WINDOW_ENABLE) && self.ly >= self.wy && self.wx <= 166
+    }
+
+    /// Whether LCDC bit 0 blanks background and window, which it only does
+    /// outside CGB mode. In CGB mode it takes away their priority instead.
+    fn bg_blanked(&self) -> bool {
+        !self.cgb && !self.lcdc(LCDC_BG_ENABLE)
+    }
+
+    /// The tile number and attributes at `column`, `row` of the tile map
+    /// selected by `high_map`.
+    fn map_entry(&self, high_map: bool, column: u8, row: u8) -> (u8, u8) {
+        let base = if high_map { 0x1c00 } else { 0x1800 };
+        let addr = base + (row as usize & 0x1f) * 32 + (column as usize & 0x1f);
+
+        let attributes = if self.cgb { self.vram[VRAM_SIZE + addr] } else { 0 };
+        (self.vram[addr], attributes)
+    }
+
+    /// The two bit planes of a tile row, flipped as the attributes ask so
+    /// the leftmost pixel is always bit 7.
+    fn tile_row(&self, addr: usize, attributes: u8) -> (u8, u8) {
+        let bank = if self.cgb && attributes & OBJ_CGB_BANK != 0 { VRAM_SIZE } else { 0 };
+        let (low, high) = (self.vram[bank + addr], self.vram[bank + addr + 1]);
+
+        if attributes & OBJ_X_FLIP != 0 {
+            (low.reverse_bits(), high.reverse_bits())
+        } else {
+            (low, high)
+        }
+    }
+
+    /// Row `row` of a background or window tile, with LCDC bit 4 choosing
+    /// between unsigned tile numbers from 0x8000 and signed ones around 0x9000.
+    fn bg_tile_row(&self, tile: u8, attributes: u8, row: u8) -> (u8, u8) {
+        let base = if self.lcdc(LCDC_TILE_DATA) {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as i32) * 16) as usize
+        };
+
+        let mut row = row & 0x07;
+        if attributes & OBJ_Y_FLIP != 0 {
+            row = 7 - row;
+        }
+
+        self.tile_row(base + row as usize * 2, attributes)
+    }
+
+    /// The object's row on the current line.
+    fn sprite_tile_row(&self, sprite: &Sprite) -> (u8, u8) {
+        let height = self.sprite_height();
+        let mut row = self.ly.wrapping_add(16).wrapping_sub(sprite.y);
+
+        if sprite.attributes & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+
+        // tall objects ignore the low bit of the tile number
+        let tile = if height == 16 { sprite.tile & 0xfe } else { sprite.tile };
+
+        self.tile_row(tile as usize * 16 + row as usize * 2, sprite.attributes)
+    }
+
+    /// Picks the pixel that ends up on screen and looks up its color.
+    fn mix(&self, bg: BgPixel, obj: Option<ObjPixel>) -> u16 {
+        let bg_color = if self.bg_blanked() { 0 } else { bg.color };
+
+        let obj = obj.filter(|obj| {
+            if obj.color == 0 || !self.lcdc(LCDC_OBJ_ENABLE) {
+                return false;
+            }
+
+            let behind = obj.attributes & OBJ_BEHIND_BG != 0 || bg.attributes & BG_PRIORITY != 0;
+
+            // in CGB mode a cleared LCDC bit 0 puts objects on top of everything
+            bg_color == 0 || !behind || (self.cgb && !self.lcdc(LCDC_BG_ENABLE))
+        });
+
+        match obj {
+            Some(obj) if self.cgb => {
+                Video::palette_color(&self.obj_palettes, obj.attributes & OBJ_CGB_PALETTE, obj.color)
+            },
+            Some(obj) => {
+                let palette = if obj.attributes & OBJ_PALETTE != 0 { self.obp1 } else { self.obp0 };
+                DMG_COLORS[shade(palette, obj.color) as usize]
+            },
+            None if self.cgb => {
+                Video::palette_color(&self.bg_palettes, bg.attributes & OBJ_CGB_PALETTE, bg_color)
+            },
+            None => DMG_COLORS[shade(self.bgp, bg_color) as usize]
+        }
+    }
+
+    fn palette_color(palettes: &[u8; PALETTE_RAM_SIZE], palette: u8, color: u8) -> u16 {
+        let index = palette as usize * 8 + color as usize * 2;
+        u16::from_le_bytes([palettes[index], palettes[index + 1]]) & 0x7fff
+    }
+
+    fn set_pixel(&mut self, x: usize, color: u16) {
+        self.framebuffer[self.ly as usize * SCREEN_WIDTH + x] = color;
+    }
+}
+
+/// The color number of pixel `x` (0 is leftmost) in a tile row.
+fn tile_color((low, high): (u8, u8), x: u8) -> u8 {
+    let bit = 7 - (x & 0x07);
+    (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01)
+}
+
+/// Looks a color number up in BGP, OBP0 or OBP1.
+fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+/// Writes through BCPD or OCPD and returns the index register, advanced if
+/// its bit 7 asks for auto increment.
+fn write_palette(palettes: &mut [u8; PALETTE_RAM_SIZE], index: u8, value: u8) -> u8 {
+    palettes[(index & 0x3f) as usize] = value;
+
+    if index & 0x80 != 0 {
+        0x80 | (index.wrapping_add(1) & 0x3f)
+    } else {
+        index
+    }
+}
+
+/// The picture processing unit. It walks through 154 lines of 456 dots each,
+/// the first 144 of which go through OAM scan, drawing and HBlank before
+/// VBlank takes up the rest.
+///
+/// | addr  | register                                       |
+/// |-------|------------------------------------------------|
+/// | FF40  | LCDC, LCD control                              |
+/// | FF41  | STAT, LCD status                               |
+/// | FF42  | SCY, background scroll Y                       |
+/// | FF43  | SCX, background scroll X                       |
+/// | FF44  | LY, current line, read only                    |
+/// | FF45  | LYC, line compare                              |
+/// | FF47  | BGP, background palette                        |
+/// | FF48  | OBP0, object palette 0                         |
+/// | FF49  | OBP1, object palette 1                         |
+/// | FF4A  | WY, window Y                                   |
+/// | FF4B  | WX, window X plus 7                            |
+/// | FF4F  | VBK, VRAM bank, CGB only                       |
+/// | FF68  | BCPS, background palette index, CGB only       |
+/// | FF69  | BCPD, background palette data, CGB only        |
+/// | FF6A  | OCPS, object palette index, CGB only           |
+/// | FF6B  | OCPD, object palette data, CGB only            |
+/// | FF6C  | OPRI, object priority mode, CGB only           |
+///
+/// The framebuffer holds one 15 bit color per pixel, red in the low bits.
+/// Outside CGB mode the four shades map to `DMG_COLORS`.
+///
+/// The STAT interrupt sources in bits 3-6 of STAT are ORed into a single
+/// line and the interrupt is only requested when that line goes high, so a
+/// source becoming active while another one already is does not fire again.
+pub struct Ppu {
+    video: Video,
+    renderer: Box<dyn Renderer>,
+    model: Model,
+    /// Only the interrupt enable bits, the rest of STAT is computed on read.
+    stat: u8,
+    stat_line: bool,
+    mode: Mode,
+    dot: u32,
+    frame_ready: bool,
+    hblank_started: bool,
+    interrupts: u8
+}
+
+impl Ppu {
+    /// `cgb` selects CGB mode, which only CGB models running colour
+    /// cartridges use.
+    pub fn new(kind: RendererKind, model: Model, cgb: bool) -> Ppu {
+        let renderer: Box<dyn Renderer> = match kind {
+            RendererKind::Scanline => Box::new(ScanlineRenderer::new()),
+            RendererKind::PixelFifo => Box::new(FifoRenderer::new())
+        };
+
+        Ppu {
+            video: Video::new(cgb),
+            renderer,
+            model,
+            stat: 0,
+            stat_line: false,
+            mode: Mode::OamScan,
+            dot: 0,
+            frame_ready: false,
+            hblank_started: false,
+            interrupts: 0
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.video.ly
+    }
+
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.video.framebuffer
+    }
+
+    /// Returns true once after each completed frame.
+    pub fn take_frame(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        ready
+    }
+
+    /// Returns true once after mode 3 of a visible line ends.
+    pub fn take_hblank(&mut self) -> bool {
+        let started = self.hblank_started;
+        self.hblank_started = false;
+        started
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.video.lcdc(LCDC_ENABLE)
+    }
+
+    /// Returns the IF bits of the interrupts requested since the last call.
+    pub fn take_interrupts(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+        interrupts
+    }
+
+    fn vram_index(&self, addr: u16) -> usize {
+        self.video.vram_bank as usize * VRAM_SIZE + (addr & 0x1fff) as usize
+    }
+
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        self.video.vram[self.vram_index(addr)]
+    }
+
+    pub fn write_vram(&mut self, addr: u16, value: u8) {
+        let index = self.vram_index(addr);
+        self.video.vram[index] = value;
+    }
+
+    pub fn read_oam(&self, addr: u16) -> u8 {
+        self.video.oam[(addr - 0xfe00) as usize]
+    }
+
+    pub fn write_oam(&mut self, addr: u16, value: u8) {
+        self.video.oam[(addr - 0xfe00) as usize] = value;
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        let video = &self.video;
+
+        match addr {
+            0xff40 => video.lcdc,
+            0xff41 => 0x80 | self.stat | self.coincidence() | self.mode as u8,
+            0xff42 => video.scy,
+            0xff43 => video.scx,
+            0xff44 => video.ly,
+            0xff45 => video.lyc,
+            0xff47 => video.bgp,
+            0xff48 => video.obp0,
+            0xff49 => video.obp1,
+            0xff4a => video.wy,
+            0xff4b => video.wx,
+            0xff4f if video.cgb => 0xfe | video.vram_bank,
+            0xff68 if video.cgb => 0x40 | video.bcps,
+            0xff69 if video.cgb => video.bg_palettes[(video.bcps & 0x3f) as usize],
+            0xff6a if video.cgb => 0x40 | video.ocps,
+            0xff6b if video.cgb => video.obj_palettes[(video.ocps & 0x3f) as usize],
+            0xff6c if video.cgb => 0xfe | video.opri,
+            _ => 0xff
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        let video = &mut self.video;
+
+        match addr {
+            0xff40 => {
+                let was_enabled = video.lcdc(LCDC_ENABLE);
+                video.lcdc = value;
+
+                match (was_enabled, video.lcdc(LCDC_ENABLE)) {
+                    (true, false) => self.turn_off(),
+                    (false, true) => {
+                        self.mode = Mode::OamScan;
+                        self.dot = 0;
+                        self.update_stat_line();
+                    },
+                    _ => {}
+                }
+            },
+            0xff41 => {
+                // on the DMG the write briefly sets every enable bit, firing the
+                // interrupt during HBlank, VBlank or on a LYC match
+                if !self.model.is_cgb() && video.lcdc(LCDC_ENABLE) {
+                    self.stat = STAT_LYC_INTERRUPT | STAT_VBLANK_INTERRUPT | STAT_HBLANK_INTERRUPT;
+                    self.update_stat_line();
+                }
+
+                self.stat = value & 0x78;
+                self.update_stat_line();
+            },
+            0xff42 => video.scy = value,
+            0xff43 => video.scx = value,
+            0xff45 => {
+                video.lyc = value;
+                self.update_stat_line();
+            },
+            0xff47 => video.bgp = value,
+            0xff48 => video.obp0 = value,
+            0xff49 => video.obp1 = value,
+            0xff4a => video.wy = value,
+            0xff4b => video.wx = value,
+            0xff4f if video.cgb => video.vram_bank = value & 0x01,
+            0xff68 if video.cgb => video.bcps = value & 0xbf,
+            0xff69 if video.cgb => video.bcps = write_palette(&mut video.bg_palettes, video.bcps, value),
+            0xff6a if video.cgb => video.ocps = value & 0xbf,
+            0xff6b if video.cgb => video.ocps = write_palette(&mut video.obj_palettes, video.ocps, value),
+            0xff6c if video.cgb => video.opri = value & 0x01,
+            _ => {}
+        }
+    }
+
+    /// With the LCD off the PPU rests at the start of line 0 and the screen
+    /// goes blank.
+    fn turn_off(&mut self) {
+        self.video.ly = 0;
+        self.video.window_line = 0;
+        self.video.framebuffer.iter_mut().for_each(|pixel| *pixel = DMG_COLORS[0]);
+        self.mode = Mode::HBlank;
+        self.dot = 0;
+        self.stat_line = false;
+    }
+
+    fn coincidence(&self) -> u8 {
+        if self.video.ly == self.video.lyc { STAT_LYC_EQUAL } else { 0 }
+    }
+
+    /// Recomputes the STAT interrupt line and requests the interrupt on a
+    /// rising edge.
+    fn update_stat_line(&mut self) {
+        let enabled = |bit: u8| self.stat & bit != 0;
+
+        let line = match self.mode {
+            Mode::HBlank => enabled(STAT_HBLANK_INTERRUPT),
+            // the OAM source also fires as VBlank starts
+            Mode::VBlank => enabled(STAT_VBLANK_INTERRUPT)
+                || (enabled(STAT_OAM_INTERRUPT) && self.video.ly as usize == SCREEN_HEIGHT && self.dot == 0),
+            Mode::OamScan => enabled(STAT_OAM_INTERRUPT),
+            Mode::Drawing => false
+        } || (enabled(STAT_LYC_INTERRUPT) && self.coincidence() != 0);
+
+        if line && !self.stat_line {
+            self.interrupts |= Interrupt::LcdStat.mask();
+        }
+
+        self.stat_line = line;
+    }
+
+    /// Advances the PPU by `cycles` dots.
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.video.lcdc(LCDC_ENABLE) {
+            return;
+        }
+
+        for _ in 0..cycles {
+            self.tick_dot();
+        }
+    }
+
+    fn tick_dot(&mut self) {
+        match self.mode {
+            Mode::OamScan => {
+                if self.dot == OAM_SCAN_DOTS - 1 {
+                    self.video.scan_oam();
+                    self.renderer.start_line(&mut self.video);
+                    self.mode = Mode::Drawing;
+                }
+            },
+            Mode::Drawing => {
+                if self.renderer.tick(&mut self.video) {
+                    self.mode = Mode::HBlank;
+                    self.hblank_started = true;
+                }
+            },
+            Mode::HBlank | Mode::VBlank => {}
+        }
+
+        self.dot += 1;
+
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.next_line();
+        }
+
+        self.update_stat_line();
+    }
+
+    fn next_line(&mut self) {
+        let video = &mut self.video;
+
+        if video.window_drawn {
+            video.window_line = video.window_line.wrapping_add(1);
+            video.window_drawn = false;
+        }
+
+        video.ly += 1;
+
+        if video.ly == LINES_PER_FRAME {
+            video.ly = 0;
+            video.window_line = 0;
+        }
+
+        self.mode = match video.ly as usize {
+            SCREEN_HEIGHT => {
+                self.frame_ready = true;
+                self.interrupts |= Interrupt::VBlank.mask();
+                Mode::VBlank
+            },
+            ly if ly < SCREEN_HEIGHT => Mode::OamScan,
+            _ => Mode::VBlank
+        };
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new(RendererKind::default(), Model::Dmg, false)
+    }
+}
@@ -0,0 +1,259 @@
+use crate::ppu::{
+    BgPixel, ObjPixel, Renderer, Sprite, Video, SCREEN_WIDTH, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_WINDOW_MAP,
+    tile_color
+};
+
+use std::collections::VecDeque;
+
+/// Dots spent on the tile fetch at the start of every line whose result is
+/// thrown away.
+const DUMMY_FETCH_DOTS: u32 = 6;
+
+/// Dots an object fetch stalls the pixel output for, once the background
+/// fetcher has a tile ready.
+const SPRITE_FETCH_DOTS: u32 = 6;
+
+/// The background fetcher reads the tile number, then the two bit planes,
+/// two dots each, and then waits until the FIFO is empty to push 8 pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push
+}
+
+/// A dot accurate model of mode 3. A fetcher fills a background FIFO a tile
+/// at a time while one pixel a dot is shifted out to the LCD, so mode 3
+/// stretches with SCX fine scroll, the window restart and object fetches,
+/// and register writes land on the pixel they were made at.
+pub struct FifoRenderer {
+    bg_fifo: VecDeque<BgPixel>,
+    sprite_fifo: VecDeque<ObjPixel>,
+    step: FetcherStep,
+    /// Whether this step is on its first or second dot.
+    step_dot: bool,
+    /// Tile column the fetcher is on, relative to SCX or the window start.
+    fetcher_x: u8,
+    tile: u8,
+    attributes: u8,
+    data_low: u8,
+    data_high: u8,
+    /// Pixels already shifted out to the LCD.
+    lcd_x: usize,
+    /// Pixels still to drop for SCX fine scroll.
+    discard: u8,
+    /// Dots left of the dummy fetch at the start of the line.
+    delay: u32,
+    window: bool,
+    /// The line's objects in the order they are fetched.
+    sprites: Vec<Sprite>,
+    next_sprite: usize,
+    /// Dots left of the object fetch in progress, once the fetcher is ready.
+    sprite_dots: Option<u32>
+}
+
+impl FifoRenderer {
+    pub fn new() -> FifoRenderer {
+        FifoRenderer {
+            bg_fifo: VecDeque::with_capacity(16),
+            sprite_fifo: VecDeque::with_capacity(8),
+            step: FetcherStep::Tile,
+            step_dot: false,
+            fetcher_x: 0,
+            tile: 0,
+            attributes: 0,
+            data_low: 0,
+            data_high: 0,
+            lcd_x: 0,
+            discard: 0,
+            delay: 0,
+            window: false,
+            sprites: Vec::new(),
+            next_sprite: 0,
+            sprite_dots: None
+        }
+    }
+
+    /// The (tile column, pixel row) the fetcher is on in its tile map.
+    fn fetch_position(&self, video: &Video) -> (u8, u8) {
+        if self.window {
+            (self.fetcher_x, video.window_line)
+        } else {
+            ((video.scx / 8).wrapping_add(self.fetcher_x), video.ly.wrapping_add(video.scy))
+        }
+    }
+
+    fn tick_fetcher(&mut self, video: &Video) {
+        if self.step != FetcherStep::Push && !self.step_dot {
+            self.step_dot = true;
+            return;
+        }
+
+        self.step_dot = false;
+
+        let (column, row) = self.fetch_position(video);
+
+        self.step = match self.step {
+            FetcherStep::Tile => {
+                let high_map = if self.window { video.lcdc(LCDC_WINDOW_MAP) } else { video.lcdc(LCDC_BG_MAP) };
+                let (tile, attributes) = video.map_entry(high_map, column, row / 8);
+                self.tile = tile;
+                self.attributes = attributes;
+                FetcherStep::DataLow
+            },
+            FetcherStep::DataLow => {
+                self.data_low = video.bg_tile_row(self.tile, self.attributes, row).0;
+                FetcherStep::DataHigh
+            },
+            FetcherStep::DataHigh => {
+                self.data_high = video.bg_tile_row(self.tile, self.attributes, row).1;
+                FetcherStep::Push
+            },
+            FetcherStep::Push => {
+                if !self.bg_fifo.is_empty() {
+                    return;
+                }
+
+                for x in 0..8 {
+                    let color = tile_color((self.data_low, self.data_high), x);
+                    self.bg_fifo.push_back(BgPixel { color, attributes: self.attributes });
+                }
+
+                self.fetcher_x = self.fetcher_x.wrapping_add(1);
+                FetcherStep::Tile
+            }
+        };
+    }
+
+    /// Starts drawing the window from here on, throwing away what the
+    /// background fetcher had prepared.
+    fn start_window(&mut self, video: &mut Video) {
+        self.window = true;
+        self.bg_fifo.clear();
+        self.step = FetcherStep::Tile;
+        self.step_dot = false;
+        self.fetcher_x = 0;
+
+        // a WX below 7 pushes the window's left edge off screen
+        if video.wx < 7 {
+            self.discard = 7 - video.wx;
+        }
+
+        video.window_drawn = true;
+    }
+
+    /// Whether the next object is due at the current pixel.
+    fn sprite_due(&self, video: &Video) -> bool {
+        video.lcdc(LCDC_OBJ_ENABLE)
+            && self.sprites.get(self.next_sprite).is_some_and(|sprite| sprite.x as usize <= self.lcd_x + 8)
+    }
+
+    /// Mixes the object into the object FIFO. Pixels already there belong to
+    /// objects fetched earlier, which win unless they are transparent or, in
+    /// CGB mode, come later in OAM.
+    fn merge_sprite(&mut self, video: &Video, sprite: Sprite) {
+        let row = video.sprite_tile_row(&sprite);
+
+        // objects hanging off the left edge start part way in
+        let skip = (self.lcd_x + 8).saturating_sub(sprite.x as usize);
+
+        while self.sprite_fifo.len() < 8 {
+            self.sprite_fifo.push_back(ObjPixel::default());
+        }
+
+        let index_priority = video.index_priority();
+
+        for (i, column) in (skip..8).enumerate() {
+            let color = tile_color(row, column as u8);
+            let existing = self.sprite_fifo[i];
+
+            if color != 0 && (existing.color == 0 || (index_priority && sprite.index < existing.index)) {
+                self.sprite_fifo[i] = ObjPixel { color, attributes: sprite.attributes, index: sprite.index };
+            }
+        }
+    }
+
+    /// Shifts one pixel out of the FIFOs and onto the LCD.
+    fn shift_pixel(&mut self, video: &mut Video) {
+        let bg = self.bg_fifo.pop_front().unwrap();
+
+        // fine scroll drops background pixels before objects are lined up
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
+        }
+
+        let obj = self.sprite_fifo.pop_front();
+        let color = video.mix(bg, obj);
+
+        video.set_pixel(self.lcd_x, color);
+        self.lcd_x += 1;
+    }
+}
+
+impl Default for FifoRenderer {
+    fn default() -> Self {
+        FifoRenderer::new()
+    }
+}
+
+impl Renderer for FifoRenderer {
+    fn start_line(&mut self, video: &mut Video) {
+        self.bg_fifo.clear();
+        self.sprite_fifo.clear();
+        self.step = FetcherStep::Tile;
+        self.step_dot = false;
+        self.fetcher_x = 0;
+        self.lcd_x = 0;
+        self.discard = video.scx & 0x07;
+        self.delay = DUMMY_FETCH_DOTS;
+        self.window = false;
+        self.sprite_dots = None;
+
+        self.sprites = video.sprites.clone();
+        self.sprites.sort_by_key(|sprite| (sprite.x, sprite.index));
+        self.next_sprite = 0;
+    }
+
+    fn tick(&mut self, video: &mut Video) -> bool {
+        if self.delay > 0 {
+            self.delay -= 1;
+            return false;
+        }
+
+        if !self.window && self.discard == 0 && !video.bg_blanked()
+            && video.window_visible() && self.lcd_x + 7 >= video.wx as usize {
+            self.start_window(video);
+        }
+
+        if self.sprite_due(video) {
+            // the fetcher finishes its tile before the object fetch starts
+            if self.sprite_dots.is_none() && (self.step != FetcherStep::Push || self.bg_fifo.is_empty()) {
+                self.tick_fetcher(video);
+                return false;
+            }
+
+            let dots = self.sprite_dots.unwrap_or(SPRITE_FETCH_DOTS) - 1;
+
+            if dots == 0 {
+                let sprite = self.sprites[self.next_sprite];
+                self.merge_sprite(video, sprite);
+                self.next_sprite += 1;
+                self.sprite_dots = None;
+            } else {
+                self.sprite_dots = Some(dots);
+            }
+
+            return false;
+        }
+
+        self.tick_fetcher(video);
+
+        if !self.bg_fifo.is_empty() {
+            self.shift_pixel(video);
+        }
+
+        self.lcd_x == SCREEN_WIDTH
+    }
+}
@@ -0,0 +1,116 @@
+use crate::ppu::{
+    BgPixel, ObjPixel, Renderer, Video, SCREEN_WIDTH, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_WINDOW_MAP, tile_color
+};
+
+/// Mode 3 takes this long when nothing stalls the pixel pipeline.
+const DRAWING_DOTS: u32 = 172;
+
+/// Draws the whole line in one go at the start of mode 3 and then just
+/// waits out a fixed length mode 3. Register writes during the line take
+/// effect on the next one, which is plenty for most games.
+pub struct ScanlineRenderer {
+    dots: u32
+}
+
+impl ScanlineRenderer {
+    pub fn new() -> ScanlineRenderer {
+        ScanlineRenderer { dots: 0 }
+    }
+
+    /// The background and window pixels of the line before palette lookup.
+    fn draw_background(video: &mut Video) -> [BgPixel; SCREEN_WIDTH] {
+        let mut pixels = [BgPixel::default(); SCREEN_WIDTH];
+
+        // outside CGB mode a cleared LCDC bit 0 blanks both background and window
+        if video.bg_blanked() {
+            return pixels;
+        }
+
+        let window_x = video.wx as i16 - 7;
+        let window = video.window_visible();
+
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            let (column, row, high_map) = if window && x as i16 >= window_x {
+                ((x as i16 - window_x) as u8, video.window_line, video.lcdc(LCDC_WINDOW_MAP))
+            } else {
+                ((x as u8).wrapping_add(video.scx), video.ly.wrapping_add(video.scy), video.lcdc(LCDC_BG_MAP))
+            };
+
+            let (tile, attributes) = video.map_entry(high_map, column / 8, row / 8);
+
+            *pixel = BgPixel {
+                color: tile_color(video.bg_tile_row(tile, attributes, row), column),
+                attributes
+            };
+        }
+
+        video.window_drawn = window;
+        pixels
+    }
+
+    /// The object pixels of the line. Where objects overlap the one with the
+    /// lower X wins, then the one earlier in OAM, or in CGB mode only the OAM
+    /// order counts. The winner hides the others even if it then ends up
+    /// behind the background.
+    fn draw_sprites(video: &Video) -> [Option<ObjPixel>; SCREEN_WIDTH] {
+        let mut pixels = [None; SCREEN_WIDTH];
+
+        let mut sprites = video.sprites.clone();
+        if !video.index_priority() {
+            sprites.sort_by_key(|sprite| (sprite.x, sprite.index));
+        }
+
+        let rows: Vec<(u8, u8)> = sprites.iter().map(|sprite| video.sprite_tile_row(sprite)).collect();
+
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            let screen_x = x as u16 + 8;
+
+            for (sprite, &row) in sprites.iter().zip(rows.iter()) {
+                let left = sprite.x as u16;
+
+                if screen_x < left || screen_x >= left + 8 {
+                    continue;
+                }
+
+                let color = tile_color(row, (screen_x - left) as u8);
+
+                if color != 0 {
+                    *pixel = Some(ObjPixel { color, attributes: sprite.attributes, index: sprite.index });
+                    break;
+                }
+            }
+        }
+
+        pixels
+    }
+}
+
+impl Default for ScanlineRenderer {
+    fn default() -> Self {
+        ScanlineRenderer::new()
+    }
+}
+
+impl Renderer for ScanlineRenderer {
+    fn start_line(&mut self, video: &mut Video) {
+        self.dots = 0;
+
+        let background = ScanlineRenderer::draw_background(video);
+
+        let sprites = if video.lcdc(LCDC_OBJ_ENABLE) {
+            ScanlineRenderer::draw_sprites(video)
+        } else {
+            [None; SCREEN_WIDTH]
+        };
+
+        for x in 0..SCREEN_WIDTH {
+            let color = video.mix(background[x], sprites[x]);
+            video.set_pixel(x, color);
+        }
+    }
+
+    fn tick(&mut self, _video: &mut Video) -> bool {
+        self.dots += 1;
+        self.dots == DRAWING_DOTS
+    }
+}
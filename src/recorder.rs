@@ -0,0 +1,79 @@
+use crate::apu::Capture;
+use crate::apu::resampler::{ Resampler, SAMPLE_RATE_44100 };
+use crate::wav::WavWriter;
+
+use std::fs::File;
+use std::io::{ self, BufWriter };
+use std::path::{ Path, PathBuf };
+
+/// Recordings are made at CD rate.
+pub const RECORDING_SAMPLE_RATE: u32 = SAMPLE_RATE_44100;
+
+type WavFile = WavWriter<BufWriter<File>>;
+
+/// Writes the APU's capture out to WAV files, the mix to the given path and
+/// with stems each channel next to it, `song.wav` giving `song.ch1.wav` up
+/// to `song.ch4.wav`.
+pub struct Recorder {
+    mix: WavFile,
+    stems: Option<Vec<WavFile>>,
+    buffer: Vec<i16>
+}
+
+fn create_wav(path: &Path) -> io::Result<WavFile> {
+    WavWriter::new(BufWriter::new(File::create(path)?), RECORDING_SAMPLE_RATE)
+}
+
+impl Recorder {
+    pub fn create(path: &Path, stems: bool) -> io::Result<Recorder> {
+        let stems = if stems {
+            Some((1..=4).map(|channel| create_wav(&Recorder::stem_path(path, channel))).collect::<io::Result<_>>()?)
+        } else {
+            None
+        };
+
+        Ok(Recorder {
+            mix: create_wav(path)?,
+            stems,
+            buffer: Vec::new()
+        })
+    }
+
+    /// Where the stem of `channel`, 1 to 4, goes for a recording at `path`.
+    pub fn stem_path(path: &Path, channel: u8) -> PathBuf {
+        path.with_extension(format!("ch{}.wav", channel))
+    }
+
+    fn write_resampled(buffer: &mut Vec<i16>, resampler: &mut Resampler, wav: &mut WavFile) -> io::Result<()> {
+        buffer.resize(resampler.available() * 2, 0);
+
+        let count = resampler.read_i16(buffer);
+        wav.write_samples(&buffer[..count * 2])
+    }
+
+    /// Writes out everything resampled so far.
+    pub fn write(&mut self, capture: &mut Capture) -> io::Result<()> {
+        Recorder::write_resampled(&mut self.buffer, &mut capture.mix, &mut self.mix)?;
+
+        if let (Some(wavs), Some(stems)) = (self.stems.as_mut(), capture.stems.as_mut()) {
+            for (wav, stem) in wavs.iter_mut().zip(stems.iter_mut()) {
+                Recorder::write_resampled(&mut self.buffer, stem, wav)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes out the rest of `capture` and completes the files.
+    pub fn finish(mut self, capture: &mut Capture) -> io::Result<()> {
+        self.write(capture)?;
+
+        self.mix.finish()?;
+
+        for wav in self.stems.into_iter().flatten() {
+            wav.finish()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU32, AtomicUsize, Ordering };
+
+/// The storage both ends share. Samples are kept as f32 bits in atomics so
+/// neither side ever has to lock.
+struct Shared {
+    samples: Box<[AtomicU32]>,
+    /// Total samples ever written and read, the positions are these modulo
+    /// the capacity.
+    written: AtomicUsize,
+    read: AtomicUsize
+}
+
+impl Shared {
+    fn len(&self) -> usize {
+        self.written.load(Ordering::Acquire).wrapping_sub(self.read.load(Ordering::Acquire))
+    }
+}
+
+/// Creates a single producer, single consumer ring buffer of f32 samples,
+/// for handing audio from the emulator thread to an audio callback.
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0)
+    });
+
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+/// The writing end of a `ring_buffer`.
+pub struct Producer {
+    shared: Arc<Shared>
+}
+
+impl Producer {
+    pub fn capacity(&self) -> usize {
+        self.shared.samples.len()
+    }
+
+    /// Samples written and not read yet.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes as many of `samples` as fit, returning how many that was.
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        let capacity = self.capacity();
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let count = samples.len().min(capacity - self.len());
+
+        for (i, &sample) in samples[..count].iter().enumerate() {
+            self.shared.samples[(written + i) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+        }
+
+        self.shared.written.store(written.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+/// The reading end of a `ring_buffer`.
+pub struct Consumer {
+    shared: Arc<Shared>
+}
+
+impl Consumer {
+    /// Samples waiting to be read.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fills `out` with as many samples as there are, returning how many
+    /// that was.
+    pub fn pop(&mut self, out: &mut [f32]) -> usize {
+        let capacity = self.shared.samples.len();
+        let read = self.shared.read.load(Ordering::Relaxed);
+        let count = out.len().min(self.len());
+
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(self.shared.samples[(read + i) % capacity].load(Ordering::Relaxed));
+        }
+
+        self.shared.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+
+/// Where battery backed cartridge RAM is kept between sessions.
+pub trait SaveStorage {
+    /// Returns the previously saved data, or None if nothing was saved yet.
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>>;
+
+    fn save(&mut self, data: &[u8]) -> io::Result<()>;
+}
+
+/// A `.sav` file, the format every other emulator uses: the raw RAM
+/// followed by any mapper specific trailer such as the MBC3 clock.
+pub struct FileStorage {
+    path: PathBuf
+}
+
+impl FileStorage {
+    pub fn new<P: AsRef<Path>>(path: P) -> FileStorage {
+        FileStorage { path: path.as_ref().to_path_buf() }
+    }
+
+    /// The save file next to a ROM, `game.gb` saves to `game.sav`.
+    pub fn for_rom<P: AsRef<Path>>(rom_path: P) -> FileStorage {
+        FileStorage::new(rom_path.as_ref().with_extension("sav"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    fn save(&mut self, data: &[u8]) -> io::Result<()> {
+        // write to the side first so a crash mid-write keeps the old save
+        let temp = self.path.with_extension("sav.tmp");
+        fs::write(&temp, data)?;
+        fs::rename(&temp, &self.path)
+    }
+}
+
+/// Keeps the save in memory. Clones share the same buffer, so a test can
+/// hand one to the emulator and inspect the RAM through another.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    data: Rc<RefCell<Option<Vec<u8>>>>
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    /// Starts out as if `data` had been saved before.
+    pub fn with_data(data: Vec<u8>) -> MemoryStorage {
+        MemoryStorage { data: Rc::new(RefCell::new(Some(data))) }
+    }
+
+    pub fn contents(&self) -> Option<Vec<u8>> {
+        self.data.borrow().clone()
+    }
+}
+
+impl SaveStorage for MemoryStorage {
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.contents())
+    }
+
+    fn save(&mut self, data: &[u8]) -> io::Result<()> {
+        *self.data.borrow_mut() = Some(data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::cartridge::header::{ fix_checksums, test_image };
+    use crate::gamelad::Gamelad;
+    use crate::mmu::Bus;
+    use crate::ppu::RendererKind;
+
+    /// An MBC1 cartridge with battery backed RAM that spins in place.
+    fn battery_cartridge() -> Cartridge {
+        let mut rom = test_image(b"SAVE", 0x03, 0x00, 0x02);
+        // JR -2
+        rom[0x0100] = 0x18;
+        rom[0x0101] = 0xfe;
+        fix_checksums(&mut rom);
+
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    fn with_storage(storage: &MemoryStorage) -> Gamelad {
+        let mut gamelad = Gamelad::from_cartridge(battery_cartridge(), None, RendererKind::Scanline, Some(Box::new(storage.clone())));
+        gamelad.reset();
+        gamelad
+    }
+
+    #[test]
+    fn memory_storage_round_trips_through_the_emulator() {
+        let storage = MemoryStorage::new();
+        let mut gamelad = with_storage(&storage);
+
+        gamelad.mmu_mut().write(0x0000, 0x0a);
+        gamelad.mmu_mut().write(0xa000, 0x42);
+        gamelad.mmu_mut().write(0xbfff, 0x24);
+        assert_eq!(storage.contents(), None);
+
+        // dirty RAM is written back within a second of emulated time
+        for _ in 0..60 {
+            gamelad.run_frame();
+        }
+
+        let saved = storage.contents().unwrap();
+        assert_eq!(saved.len(), 0x2000);
+        assert_eq!((saved[0], saved[0x1fff]), (0x42, 0x24));
+
+        gamelad.mmu_mut().write(0xa001, 0x99);
+        drop(gamelad);
+        assert_eq!(storage.contents().unwrap()[1], 0x99);
+
+        let mut reloaded = with_storage(&storage);
+        reloaded.mmu_mut().write(0x0000, 0x0a);
+        assert_eq!(reloaded.mmu().read(0xa000), 0x42);
+        assert_eq!(reloaded.mmu().read(0xa001), 0x99);
+        assert_eq!(reloaded.mmu().read(0xbfff), 0x24);
+    }
+
+    #[test]
+    fn clean_ram_is_not_saved_periodically() {
+        let storage = MemoryStorage::new();
+        let mut gamelad = with_storage(&storage);
+
+        // RAM is disabled, so this write goes nowhere
+        gamelad.mmu_mut().write(0xa000, 0x42);
+
+        for _ in 0..60 {
+            gamelad.run_frame();
+        }
+
+        assert_eq!(storage.contents(), None);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::gamelad::Gamelad;
+use crate::ring_buffer::{ ring_buffer, Consumer, Producer };
+
+use sdl2::AudioSubsystem;
+use sdl2::audio::{ AudioCallback, AudioDevice, AudioSpecDesired };
+
+const SAMPLE_RATE: i32 = 48_000;
+
+/// Stereo samples SDL asks for per callback.
+const DEVICE_SAMPLES: u16 = 1024;
+
+/// Stereo samples the ring buffer holds. It is kept about half full, which
+/// makes for around 85 ms of latency at 48 kHz.
+const BUFFER_SAMPLES: usize = 8192;
+
+/// The furthest the resampling rate is pulled away from the device rate,
+/// small enough that the pitch change can't be heard.
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// Runs on SDL's audio thread and only ever touches the ring buffer.
+struct Playback {
+    consumer: Consumer
+}
+
+impl AudioCallback for Playback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let count = self.consumer.pop(out);
+
+        // an underrun plays silence rather than stale samples
+        for sample in out[count..].iter_mut() {
+            *sample = 0.0;
+        }
+    }
+}
+
+/// Plays a `Gamelad`'s audio on the default SDL output device.
+///
+/// The emulator runs in step with the video, which never quite matches the
+/// audio device's clock. Rather than letting the buffer run dry or overflow,
+/// `queue` looks at how full it is each time and speeds the resampling up
+/// or down by a fraction of a percent to steer it back to half full.
+pub struct SdlAudio {
+    device: AudioDevice<Playback>,
+    producer: Producer,
+    sample_rate: f64,
+    scratch: Vec<f32>
+}
+
+impl SdlAudio {
+    pub fn open(audio: &AudioSubsystem) -> Result<SdlAudio, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(2),
+            samples: Some(DEVICE_SAMPLES)
+        };
+
+        let (producer, consumer) = ring_buffer(BUFFER_SAMPLES * 2);
+        let device = audio.open_playback(None, &desired, |_| Playback { consumer })?;
+        let sample_rate = device.spec().freq as f64;
+
+        device.resume();
+
+        Ok(SdlAudio {
+            device,
+            producer,
+            sample_rate,
+            scratch: Vec::new()
+        })
+    }
+
+    /// The rate the device plays at.
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn pause(&self) {
+        self.device.pause();
+    }
+
+    pub fn resume(&self) {
+        self.device.resume();
+    }
+
+    /// Moves the samples `gamelad` produced since the last call over to the
+    /// audio thread and retunes its resampler for the buffer's fill level.
+    /// Meant to be called once a frame.
+    pub fn queue(&mut self, gamelad: &mut Gamelad) {
+        let available = gamelad.audio_mut().available();
+        self.scratch.resize(available * 2, 0.0);
+
+        let count = gamelad.read_audio_f32(&mut self.scratch);
+
+        // whatever doesn't fit is dropped, the rate control keeps that rare
+        self.producer.push(&self.scratch[..count * 2]);
+
+        let fill = self.producer.len() as f64 / self.producer.capacity() as f64;
+        let rate = self.sample_rate * (1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill));
+
+        gamelad.audio_mut().set_sample_rate(rate);
+    }
+}
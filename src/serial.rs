@@ -0,0 +1,97 @@
+use crate::cpu::interrupts::Interrupt;
+
+/// Cycles per bit at the internal 8192 Hz clock.
+const BIT_CYCLES: u32 = 512;
+
+/// Cycles per bit with the CGB's fast clock, 262144 Hz.
+const FAST_BIT_CYCLES: u32 = 16;
+
+/// The link port, with nothing plugged in.
+///
+/// | addr  | register                                               |
+/// |-------|--------------------------------------------------------|
+/// | FF01  | SB, the byte being shifted out                         |
+/// | FF02  | SC, bit 7 start, bit 1 fast clock (CGB), bit 0 master  |
+///
+/// A transfer on the internal clock shifts SB out over 8 bits, shifting in
+/// 0xFF from the empty port, then clears SC bit 7 and raises the serial
+/// interrupt. Bytes sent are kept in `output`, which is how test ROMs report
+/// their results. On the external clock a transfer never finishes.
+pub struct Serial {
+    cgb: bool,
+    sb: u8,
+    sc: u8,
+    /// Cycles left of the transfer on the internal clock.
+    remaining: u32,
+    output: Vec<u8>,
+    interrupts: u8
+}
+
+impl Serial {
+    pub fn new(cgb: bool) -> Serial {
+        Serial {
+            cgb,
+            sb: 0,
+            sc: 0,
+            remaining: 0,
+            output: Vec::new(),
+            interrupts: 0
+        }
+    }
+
+    /// Returns the bytes sent since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Returns the interrupts raised since the last call as IF bits.
+    pub fn take_interrupts(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+        interrupts
+    }
+
+    /// Advances a running transfer by `cycles` CPU cycles, the serial clock
+    /// speeds up with the CPU.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        self.remaining = self.remaining.saturating_sub(cycles);
+
+        if self.remaining == 0 {
+            self.output.push(self.sb);
+            self.sb = 0xff;
+            self.sc &= 0x7f;
+            self.interrupts |= Interrupt::Serial.mask();
+        }
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0xff01 => self.sb,
+            0xff02 if self.cgb => 0x7c | self.sc,
+            0xff02 => 0x7e | self.sc,
+            _ => 0xff
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xff01 => self.sb = value,
+            0xff02 => {
+                self.sc = if self.cgb { value & 0x83 } else { value & 0x81 };
+
+                self.remaining = if self.sc & 0x81 != 0x81 {
+                    0
+                } else if self.sc & 0x02 != 0 {
+                    8 * FAST_BIT_CYCLES
+                } else {
+                    8 * BIT_CYCLES
+                };
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::gamelad::Gamelad;
+use crate::mmu::Bus;
+
+use std::fmt;
+
+/// Where Blargg's tests with cartridge RAM keep their status, with the
+/// signature after it and the text they print from 0xA004.
+const STATUS_ADDR: u16 = 0xa000;
+const SIGNATURE_ADDR: u16 = 0xa001;
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const TEXT_ADDR: u16 = 0xa004;
+
+/// The status byte while a test is still running.
+const STATUS_RUNNING: u8 = 0x80;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Failed with the result code the test gave, if it gave one.
+    Failed(Option<u8>),
+    TimedOut
+}
+
+/// What a test ROM run came to, along with everything it printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestReport {
+    pub outcome: Outcome,
+    pub output: String,
+    pub frames: u32
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.output.trim_end())?;
+
+        match self.outcome {
+            Outcome::Passed => write!(f, "passed after {} frames", self.frames),
+            Outcome::Failed(Some(code)) => write!(f, "failed with code {} after {} frames", code, self.frames),
+            Outcome::Failed(None) => write!(f, "failed after {} frames", self.frames),
+            Outcome::TimedOut => write!(f, "timed out after {} frames", self.frames)
+        }
+    }
+}
+
+/// Runs a test ROM from reset without a display for up to `max_frames`
+/// frames and works out whether it passed. Blargg's tests report either as
+/// text over the serial port, like cpu_instrs, or through a status byte in
+/// cartridge RAM, like dmg_sound, and both are watched.
+pub fn run(gamelad: &mut Gamelad, max_frames: u32) -> TestReport {
+    let mut serial = Vec::new();
+
+    gamelad.reset();
+
+    for frame in 1..=max_frames {
+        gamelad.run_frame();
+        serial.extend(gamelad.mmu_mut().serial_mut().take_output());
+
+        if let Some(report) = memory_report(gamelad, frame) {
+            return report;
+        }
+
+        let output = String::from_utf8_lossy(&serial).into_owned();
+
+        let outcome = if output.contains("Passed") {
+            Outcome::Passed
+        } else if output.contains("Failed") {
+            Outcome::Failed(None)
+        } else {
+            continue;
+        };
+
+        return TestReport { outcome, output, frames: frame };
+    }
+
+    TestReport {
+        outcome: Outcome::TimedOut,
+        output: String::from_utf8_lossy(&serial).into_owned(),
+        frames: max_frames
+    }
+}
+
+/// The result a test left in cartridge RAM, once it has finished.
+fn memory_report(gamelad: &Gamelad, frames: u32) -> Option<TestReport> {
+    let mmu = gamelad.mmu();
+
+    let signed = SIGNATURE.iter().enumerate().all(|(i, &byte)| mmu.read(SIGNATURE_ADDR + i as u16) == byte);
+    let status = mmu.read(STATUS_ADDR);
+
+    if !signed || status == STATUS_RUNNING {
+        return None;
+    }
+
+    let text: Vec<u8> = (TEXT_ADDR..0xc000)
+        .map(|addr| mmu.read(addr))
+        .take_while(|&byte| byte != 0)
+        .collect();
+
+    let outcome = if status == 0 { Outcome::Passed } else { Outcome::Failed(Some(status)) };
+
+    Some(TestReport {
+        outcome,
+        output: String::from_utf8_lossy(&text).into_owned(),
+        frames
+    })
+}
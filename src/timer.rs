@@ -0,0 +1,292 @@
+use crate::cpu::interrupts::Interrupt;
+
+/// The timer works in whole M-cycles.
+const STEP_CYCLES: u32 = 4;
+
+/// The counter bit whose falling edge steps the APU frame sequencer, DIV
+/// bit 4 at normal speed and bit 5 in double speed.
+const APU_BIT: u16 = 0x1000;
+const APU_BIT_DOUBLE_SPEED: u16 = 0x2000;
+
+/// The divider and the programmable timer. Both run off one 16 bit counter
+/// that goes up every CPU cycle; DIV is its upper byte.
+///
+/// | addr  | register                                       |
+/// |-------|------------------------------------------------|
+/// | FF04  | DIV, counter bits 8-15, writing resets it      |
+/// | FF05  | TIMA, the timer counter                        |
+/// | FF06  | TMA, reloaded into TIMA when it overflows      |
+/// | FF07  | TAC, bit 2 enable and bits 0-1 clock select    |
+///
+/// TIMA counts the falling edges of one counter bit picked by TAC, ANDed
+/// with the enable bit. Anything that drops that signal counts, so writing
+/// DIV or TAC can tick TIMA too. On overflow TIMA reads 0 for one M-cycle
+/// before TMA is loaded and the interrupt is raised. Writing TIMA during
+/// that cycle cancels the reload, while in the cycle of the reload TIMA
+/// writes are dropped and TMA writes go through to TIMA as well.
+///
+/// The counter also clocks the APU frame sequencer, see `take_apu_clocks`.
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    /// TIMA overflowed and gets reloaded on the next M-cycle.
+    overflow: bool,
+    /// TIMA was reloaded this M-cycle.
+    reloading: bool,
+    cycles: u32,
+    interrupts: u8,
+    apu_bit: u16,
+    apu_clocks: u32
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            overflow: false,
+            reloading: false,
+            cycles: 0,
+            interrupts: 0,
+            apu_bit: APU_BIT,
+            apu_clocks: 0
+        }
+    }
+
+    /// The internal 16 bit counter.
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    /// Sets the internal counter without clocking TIMA, for starting out
+    /// where the boot ROM leaves it.
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    /// Returns the interrupts raised since the last call as IF bits.
+    pub fn take_interrupts(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+        interrupts
+    }
+
+    /// Picks the DIV bit that clocks the APU for the CPU speed.
+    pub fn set_double_speed(&mut self, double_speed: bool) {
+        self.apu_bit = if double_speed { APU_BIT_DOUBLE_SPEED } else { APU_BIT };
+    }
+
+    /// Returns how many times the frame sequencer is due to step since the
+    /// last call.
+    pub fn take_apu_clocks(&mut self) -> u32 {
+        let clocks = self.apu_clocks;
+        self.apu_clocks = 0;
+        clocks
+    }
+
+    /// The signal TIMA counts the falling edges of.
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            _ => 7
+        };
+
+        self.tac & 0x04 != 0 && self.counter & (1 << bit) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.overflow = overflow;
+    }
+
+    fn set_counter_clocked(&mut self, counter: u16) {
+        let signal = self.signal();
+        let apu_signal = self.counter & self.apu_bit != 0;
+        self.counter = counter;
+
+        if signal && !self.signal() {
+            self.increment_tima();
+        }
+
+        if apu_signal && self.counter & self.apu_bit == 0 {
+            self.apu_clocks += 1;
+        }
+    }
+
+    fn step(&mut self) {
+        self.reloading = false;
+
+        if self.overflow {
+            self.overflow = false;
+            self.reloading = true;
+            self.tima = self.tma;
+            self.interrupts |= Interrupt::Timer.mask();
+        }
+
+        self.set_counter_clocked(self.counter.wrapping_add(STEP_CYCLES as u16));
+    }
+
+    /// Advances the counter by `cycles` CPU cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles;
+
+        while self.cycles >= STEP_CYCLES {
+            self.cycles -= STEP_CYCLES;
+            self.step();
+        }
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0xff04 => (self.counter >> 8) as u8,
+            0xff05 => self.tima,
+            0xff06 => self.tma,
+            0xff07 => 0xf8 | self.tac,
+            _ => 0xff
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xff04 => self.set_counter_clocked(0),
+            // the reload wins over a TIMA write in the same cycle
+            0xff05 if !self.reloading => {
+                self.tima = value;
+                self.overflow = false;
+            },
+            0xff06 => {
+                self.tma = value;
+
+                if self.reloading {
+                    self.tima = value;
+                }
+            },
+            0xff07 => {
+                let signal = self.signal();
+                self.tac = value & 0x07;
+
+                if signal && !self.signal() {
+                    self.increment_tima();
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timer counting the falling edges of counter bit 3, every 16 cycles.
+    fn fast_timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write_register(0xff07, 0x05);
+        timer
+    }
+
+    /// A fast timer that has just overflowed, with 0xab in TMA.
+    fn overflowed_timer() -> Timer {
+        let mut timer = fast_timer();
+        timer.write_register(0xff06, 0xab);
+        timer.write_register(0xff05, 0xff);
+        timer.tick(16);
+        timer
+    }
+
+    #[test]
+    fn div_write_ticks_tima_on_a_falling_edge() {
+        let mut timer = fast_timer();
+
+        timer.set_counter(0x0008);
+        timer.write_register(0xff04, 0x00);
+        assert_eq!(timer.read_register(0xff05), 1);
+        assert_eq!(timer.counter(), 0);
+
+        timer.set_counter(0x0010);
+        timer.write_register(0xff04, 0x00);
+        assert_eq!(timer.read_register(0xff05), 1);
+    }
+
+    #[test]
+    fn tac_change_that_drops_the_signal_ticks_tima() {
+        let mut timer = fast_timer();
+        timer.set_counter(0x0008);
+
+        // bit 9 is low, so switching to it is a falling edge
+        timer.write_register(0xff07, 0x04);
+        assert_eq!(timer.read_register(0xff05), 1);
+
+        // and so is turning the timer off while the bit is high
+        timer.write_register(0xff07, 0x05);
+        timer.write_register(0xff07, 0x01);
+        assert_eq!(timer.read_register(0xff05), 2);
+
+        // turning it on isn't
+        timer.write_register(0xff07, 0x05);
+        assert_eq!(timer.read_register(0xff05), 2);
+    }
+
+    #[test]
+    fn overflow_reads_zero_for_a_cycle_before_the_reload() {
+        let mut timer = overflowed_timer();
+        assert_eq!(timer.read_register(0xff05), 0x00);
+        assert_eq!(timer.take_interrupts(), 0);
+
+        timer.tick(4);
+        assert_eq!(timer.read_register(0xff05), 0xab);
+        assert_eq!(timer.take_interrupts(), Interrupt::Timer.mask());
+    }
+
+    #[test]
+    fn tima_write_before_the_reload_cancels_it() {
+        let mut timer = overflowed_timer();
+        timer.write_register(0xff05, 0x42);
+
+        timer.tick(4);
+        assert_eq!(timer.read_register(0xff05), 0x42);
+        assert_eq!(timer.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn tima_write_in_the_reload_cycle_is_dropped() {
+        let mut timer = overflowed_timer();
+        timer.tick(4);
+
+        timer.write_register(0xff05, 0x42);
+        assert_eq!(timer.read_register(0xff05), 0xab);
+
+        // a cycle later it goes through again
+        timer.tick(4);
+        timer.write_register(0xff05, 0x42);
+        assert_eq!(timer.read_register(0xff05), 0x42);
+    }
+
+    #[test]
+    fn tma_write_in_the_reload_cycle_reaches_tima() {
+        let mut timer = overflowed_timer();
+        timer.tick(4);
+
+        timer.write_register(0xff06, 0x55);
+        assert_eq!(timer.read_register(0xff05), 0x55);
+        assert_eq!(timer.read_register(0xff06), 0x55);
+
+        timer.tick(4);
+        timer.write_register(0xff06, 0x66);
+        assert_eq!(timer.read_register(0xff05), 0x55);
+        assert_eq!(timer.read_register(0xff06), 0x66);
+    }
+}
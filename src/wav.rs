@@ -0,0 +1,110 @@
+use std::convert::TryFrom;
+use std::io::{ self, Seek, SeekFrom, Write };
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes 16 bit stereo PCM as a WAV file. The sizes in the header are
+/// filled in by `finish`, a file that is never finished still plays but
+/// claims to be empty.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    /// Bytes of sample data written so far.
+    data_size: u32
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        let mut wav = WavWriter {
+            writer,
+            sample_rate,
+            data_size: 0
+        };
+
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        self.writer.write_all(b"RIFF")?;
+        self.writer.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVE")?;
+
+        self.writer.write_all(b"fmt ")?;
+        self.writer.write_all(&16u32.to_le_bytes())?;
+        // uncompressed PCM
+        self.writer.write_all(&1u16.to_le_bytes())?;
+        self.writer.write_all(&CHANNELS.to_le_bytes())?;
+        self.writer.write_all(&self.sample_rate.to_le_bytes())?;
+        self.writer.write_all(&byte_rate.to_le_bytes())?;
+        self.writer.write_all(&block_align.to_le_bytes())?;
+        self.writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&self.data_size.to_le_bytes())
+    }
+
+    /// Appends interleaved left and right samples. Fails without writing
+    /// anything once the file would outgrow the 32 bit sizes in the header.
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+
+        let data_size = u32::try_from(bytes.len()).ok()
+            .and_then(|len| self.data_size.checked_add(len))
+            .filter(|size| size.checked_add(HEADER_SIZE - 8).is_some())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "WAV larger than 4 GiB"))?;
+
+        self.writer.write_all(&bytes)?;
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    /// Fills in the header and hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    #[test]
+    fn finish_fills_in_the_sizes() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.write_samples(&[1, -1, 2, -2]).unwrap();
+
+        let bytes = wav.finish().unwrap().into_inner();
+        assert_eq!(bytes.len(), HEADER_SIZE as usize + 8);
+        assert_eq!(le_u32(&bytes, 4), HEADER_SIZE - 8 + 8);
+        assert_eq!(le_u32(&bytes, 40), 8);
+        assert_eq!(bytes[44..], [1, 0, 0xff, 0xff, 2, 0, 0xfe, 0xff]);
+    }
+
+    #[test]
+    fn samples_past_4_gib_are_refused() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.data_size = u32::MAX - (HEADER_SIZE - 8) - 4;
+
+        wav.write_samples(&[1, 2]).unwrap();
+
+        let err = wav.write_samples(&[3, 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(wav.data_size, u32::MAX - (HEADER_SIZE - 8));
+        assert_eq!(wav.writer.get_ref().len(), HEADER_SIZE as usize + 4);
+    }
+}